@@ -0,0 +1,71 @@
+// UTC calendar helpers for scheduled series expiries.
+// Day arithmetic follows Howard Hinnant's civil date algorithms.
+
+const SECONDS_PER_DAY: u64 = 86400;
+const SECONDS_PER_WEEK: u64 = 7 * SECONDS_PER_DAY;
+
+// Expiries settle at 08:00 UTC
+const EXPIRY_TIME_OF_DAY: u64 = 8 * 3600;
+
+// 1970-01-02 was the first Friday after the unix epoch
+const FIRST_FRIDAY_EXPIRY: u64 = SECONDS_PER_DAY + EXPIRY_TIME_OF_DAY;
+
+const FRIDAY: i64 = 5; // 0 = Sunday
+
+// First Friday 08:00 UTC strictly after `now`
+pub fn next_weekly_expiry(now: u64) -> u64 {
+    if now < FIRST_FRIDAY_EXPIRY {
+        return FIRST_FRIDAY_EXPIRY;
+    }
+    let weeks = (now - FIRST_FRIDAY_EXPIRY) / SECONDS_PER_WEEK + 1;
+    FIRST_FRIDAY_EXPIRY + weeks * SECONDS_PER_WEEK
+}
+
+// Last Friday of the given month at 08:00 UTC
+pub fn monthly_expiry(year: i64, month: u32) -> u64 {
+    let (next_year, next_month) = next_month(year, month);
+    let last_day = days_from_civil(next_year, next_month, 1) - 1;
+    let weekday = (last_day + 4).rem_euclid(7); // the epoch was a Thursday
+    let last_friday = last_day - (weekday - FRIDAY).rem_euclid(7);
+    last_friday as u64 * SECONDS_PER_DAY + EXPIRY_TIME_OF_DAY
+}
+
+// (year, month) containing the timestamp
+pub fn month_of(timestamp: u64) -> (i64, u32) {
+    civil_from_days((timestamp / SECONDS_PER_DAY) as i64)
+}
+
+pub fn next_month(year: i64, month: u32) -> (i64, u32) {
+    if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    }
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month)
+}
@@ -0,0 +1,265 @@
+// Checked fixed-point arithmetic for prices and amounts scaled by 1e7.
+//
+// `a * b / d` is computed with a 256-bit intermediate product, so it only fails when the
+// final result does not fit in an i128. Callers pick the rounding direction and should
+// always round in the pool's favor: up for amounts the pool receives or locks, down for
+// amounts it pays out.
+use crate::OptionsError;
+use soroban_sdk::{panic_with_error, Env};
+
+// Fixed-point scale shared by prices, strikes and option amounts
+pub const SCALE: i128 = 10_000_000;
+
+// Decimal places implied by SCALE
+pub const SCALE_DECIMALS: u32 = 7;
+
+// Denominator for rates expressed in basis points
+pub const BPS: i128 = 10_000;
+
+const LOW_MASK: u128 = u64::MAX as u128;
+
+// a + b
+pub fn add(env: &Env, a: i128, b: i128) -> i128 {
+    a.checked_add(b)
+        .unwrap_or_else(|| panic_with_error!(env, OptionsError::ArithmeticOverflow))
+}
+
+// a - b
+pub fn sub(env: &Env, a: i128, b: i128) -> i128 {
+    a.checked_sub(b)
+        .unwrap_or_else(|| panic_with_error!(env, OptionsError::ArithmeticOverflow))
+}
+
+// a * b
+pub fn mul(env: &Env, a: i128, b: i128) -> i128 {
+    a.checked_mul(b)
+        .unwrap_or_else(|| panic_with_error!(env, OptionsError::ArithmeticOverflow))
+}
+
+// a * b / d rounded toward negative infinity
+pub fn mul_div_floor(env: &Env, a: i128, b: i128, d: i128) -> i128 {
+    checked_mul_div_floor(a, b, d)
+        .unwrap_or_else(|| panic_with_error!(env, OptionsError::ArithmeticOverflow))
+}
+
+// a * b / d rounded toward positive infinity
+pub fn mul_div_ceil(env: &Env, a: i128, b: i128, d: i128) -> i128 {
+    checked_mul_div_ceil(a, b, d)
+        .unwrap_or_else(|| panic_with_error!(env, OptionsError::ArithmeticOverflow))
+}
+
+// Value of `amount` at `price` in units of a token with `decimals` decimals, rounded down
+pub fn value_floor(env: &Env, price: i128, amount: i128, decimals: u32) -> i128 {
+    convert_floor(env, price, amount, SCALE, SCALE_DECIMALS, decimals)
+}
+
+// Value of `amount` at `price` in units of a token with `decimals` decimals, rounded up
+pub fn value_ceil(env: &Env, price: i128, amount: i128, decimals: u32) -> i128 {
+    convert_ceil(env, price, amount, SCALE, SCALE_DECIMALS, decimals)
+}
+
+// a * num / den, moved from `from` to `to` decimal places, rounded down
+pub fn convert_floor(env: &Env, a: i128, num: i128, den: i128, from: u32, to: u32) -> i128 {
+    let (num, den) = rescale(env, num, den, from, to);
+    mul_div_floor(env, a, num, den)
+}
+
+// a * num / den, moved from `from` to `to` decimal places, rounded up
+pub fn convert_ceil(env: &Env, a: i128, num: i128, den: i128, from: u32, to: u32) -> i128 {
+    let (num, den) = rescale(env, num, den, from, to);
+    mul_div_ceil(env, a, num, den)
+}
+
+// Folds the decimal shift into the ratio so the result is rounded only once
+fn rescale(env: &Env, num: i128, den: i128, from: u32, to: u32) -> (i128, i128) {
+    let shifted = if to >= from {
+        10i128
+            .checked_pow(to - from)
+            .and_then(|factor| num.checked_mul(factor))
+            .map(|num| (num, den))
+    } else {
+        10i128
+            .checked_pow(from - to)
+            .and_then(|factor| den.checked_mul(factor))
+            .map(|den| (num, den))
+    };
+    shifted.unwrap_or_else(|| panic_with_error!(env, OptionsError::ArithmeticOverflow))
+}
+
+// Round to the nearest multiple of `tick`, halves rounding up
+pub fn round_to_tick(env: &Env, value: i128, tick: i128) -> i128 {
+    mul(env, add(env, value, tick / 2).div_euclid(tick), tick)
+}
+
+pub fn checked_mul_div_floor(a: i128, b: i128, d: i128) -> Option<i128> {
+    mul_div(a, b, d, false)
+}
+
+pub fn checked_mul_div_ceil(a: i128, b: i128, d: i128) -> Option<i128> {
+    mul_div(a, b, d, true)
+}
+
+fn mul_div(a: i128, b: i128, d: i128, round_up: bool) -> Option<i128> {
+    if d == 0 {
+        return None;
+    }
+
+    let negative = (a < 0) ^ (b < 0) ^ (d < 0);
+    let (hi, lo) = widening_mul(a.unsigned_abs(), b.unsigned_abs());
+    let divisor = d.unsigned_abs();
+
+    // The quotient would need more than 128 bits
+    if hi >= divisor {
+        return None;
+    }
+
+    let (quotient, remainder) = div_wide(hi, lo, divisor);
+
+    // Floor rounds negative magnitudes up, ceil rounds positive magnitudes up
+    let magnitude = if remainder != 0 && round_up != negative {
+        quotient.checked_add(1)?
+    } else {
+        quotient
+    };
+
+    if negative {
+        if magnitude == i128::MIN.unsigned_abs() {
+            Some(i128::MIN)
+        } else {
+            i128::try_from(magnitude).ok().map(|m| -m)
+        }
+    } else {
+        i128::try_from(magnitude).ok()
+    }
+}
+
+// Full 256-bit product of two u128 values as (high, low)
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    let (a_hi, a_lo) = (a >> 64, a & LOW_MASK);
+    let (b_hi, b_lo) = (b >> 64, b & LOW_MASK);
+
+    let low = a_lo * b_lo;
+    let cross_a = a_lo * b_hi;
+    let cross_b = a_hi * b_lo;
+    let high = a_hi * b_hi;
+
+    let middle = (low >> 64) + (cross_a & LOW_MASK) + (cross_b & LOW_MASK);
+    let lo = (low & LOW_MASK) | (middle << 64);
+    let hi = high + (cross_a >> 64) + (cross_b >> 64) + (middle >> 64);
+    (hi, lo)
+}
+
+// (hi, lo) / divisor for hi < divisor, as (quotient, remainder)
+fn div_wide(hi: u128, lo: u128, divisor: u128) -> (u128, u128) {
+    if hi == 0 {
+        return (lo / divisor, lo % divisor);
+    }
+
+    let mut remainder = hi;
+    let mut quotient = 0u128;
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((lo >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= divisor {
+            remainder = remainder.wrapping_sub(divisor);
+            quotient |= 1;
+        }
+    }
+    (quotient, remainder)
+}
+
+// Transcendental functions evaluate at 1e18 internally and return SCALE results
+const WAD: i128 = 1_000_000_000_000_000_000;
+const WAD_PER_SCALE: i128 = WAD / SCALE;
+const LN_2_WAD: i128 = 693_147_180_559_945_309;
+
+// Natural log of a positive value
+pub fn ln(env: &Env, x: i128) -> i128 {
+    if x <= 0 {
+        panic_with_error!(env, OptionsError::ArithmeticOverflow);
+    }
+    let mut m = x
+        .checked_mul(WAD_PER_SCALE)
+        .unwrap_or_else(|| panic_with_error!(env, OptionsError::ArithmeticOverflow));
+
+    // x = m * 2^k with m in [1, 2)
+    let mut k = 0i128;
+    while m >= 2 * WAD {
+        m >>= 1;
+        k += 1;
+    }
+    while m < WAD {
+        m <<= 1;
+        k -= 1;
+    }
+
+    // ln(m) = 2 * atanh(z) with z = (m - 1) / (m + 1) <= 1/3
+    let z = (m - WAD) * WAD / (m + WAD);
+    let z_squared = z * z / WAD;
+    let mut term = z;
+    let mut sum = 0i128;
+    let mut n = 1i128;
+    while term != 0 {
+        sum += term / n;
+        term = term * z_squared / WAD;
+        n += 2;
+    }
+
+    (k * LN_2_WAD + 2 * sum).div_euclid(WAD_PER_SCALE)
+}
+
+// e^x, flushing to zero below e^-40
+pub fn exp(env: &Env, x: i128) -> i128 {
+    if x < -40 * SCALE {
+        return 0;
+    }
+    if x > 80 * SCALE {
+        panic_with_error!(env, OptionsError::ArithmeticOverflow);
+    }
+
+    // e^x = 2^k * e^r with r in [0, ln 2)
+    let x_wad = x * WAD_PER_SCALE;
+    let k = x_wad.div_euclid(LN_2_WAD);
+    let r = x_wad - k * LN_2_WAD;
+
+    let mut term = WAD;
+    let mut sum = WAD;
+    let mut n = 1i128;
+    while term != 0 {
+        term = term * r / WAD / n;
+        sum += term;
+        n += 1;
+    }
+
+    let scaled = if k >= 0 {
+        sum.checked_mul(1i128 << k)
+            .unwrap_or_else(|| panic_with_error!(env, OptionsError::ArithmeticOverflow))
+    } else {
+        sum >> -k
+    };
+    scaled / WAD_PER_SCALE
+}
+
+// Square root of a non-negative value, rounded down
+pub fn sqrt(env: &Env, x: i128) -> i128 {
+    let radicand = x
+        .checked_mul(SCALE)
+        .filter(|value| *value >= 0)
+        .unwrap_or_else(|| panic_with_error!(env, OptionsError::ArithmeticOverflow));
+    isqrt(radicand as u128) as i128
+}
+
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = 1u128 << (128 - n.leading_zeros()).div_ceil(2);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
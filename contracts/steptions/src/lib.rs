@@ -1,9 +1,10 @@
 #![no_std]
+// Entry points take flat arguments, which the SDK's generated argument builders repeat
+#![allow(clippy::too_many_arguments)]
 use sep_40_oracle::{Asset, PriceData, PriceFeedClient, PriceFeedTrait};
 use soroban_sdk::{
     contract, contractimpl, contractmeta, contracttype, log, panic_with_error, symbol_short,
-    token::{self, TokenClient},
-    Address, Env, Error, Map, String, Symbol, Vec,
+    token::TokenClient, xdr::ToXdr, Address, BytesN, Env, Error, String, Symbol, Vec,
 };
 
 // Contract metadata
@@ -12,6 +13,15 @@ contractmeta!(
     val = "Multi-Pool American-Style Options Trading Platform - Pool-based, cash-settled options contract"
 );
 
+mod calendar;
+mod fixed_point;
+mod pricing;
+
+use fixed_point::{
+    add, convert_ceil, convert_floor, mul, mul_div_ceil, mul_div_floor, round_to_tick, sub,
+    value_ceil, value_floor, BPS, SCALE, SCALE_DECIMALS,
+};
+
 #[contract]
 pub struct OptionsContract;
 
@@ -24,18 +34,78 @@ pub enum DataKey {
 
     // Pool management
     PoolCounter,
-    Pool(u64),                    // Pool ID -> PoolData
-    PoolExists(Address, Address), // (stable_token, underlying_asset) -> pool_id
+    Pool(u64),                   // Pool ID -> PoolData
+    PairPools(Address, Address), // (stable_token, underlying_asset) -> pool IDs
 
     // Pool-specific data
     PoolTotalLiquidity(u64),
     PoolLockedCollateral(u64),
     PoolTotalLpShares(u64),
     PoolLpShares(u64, Address), // (pool_id, user) -> shares
+    PoolWithdrawalQueue(u64),   // Pool ID -> FIFO share redemptions waiting for collateral
+    PoolQueuedShares(u64),      // Pool ID -> total shares escrowed in the queue
+
+    // Underlying inventory backing physically settled options
+    PoolUnderlyingLiquidity(u64),
+    PoolLockedUnderlying(u64),
+    PoolUnderlyingProceeds(u64), // stable paid in by call exercises, owed to underlying LPs
+    PoolDeliveredUnderlying(u64), // underlying paid in by put exercises, owed to stable LPs
+    PoolUnderlyingTotalShares(u64),
+    PoolUnderlyingLpShares(u64, Address), // (pool_id, user) -> shares
 
     // Options
     OptionCounter,
     Option(u64),
+    PoolOpenOptions(u64), // Pool ID -> active options written by the pool
+
+    // Option series registry
+    SeriesCounter,
+    Series(u64),                                           // Series ID -> SeriesData
+    SeriesExists(u64, OptionType, i128, u64, OptionStyle), // (pool_id, type, strike, expiry, style) -> series_id
+    PoolSeries(u64),                                       // Pool ID -> listed series IDs
+    PoolArchivedSeries(u64),                               // Pool ID -> archived series IDs
+    SeriesSchedule(u64), // Pool ID -> SeriesSchedule used by roll_series
+
+    // Strategy vaults
+    VaultCounter,
+    Vault(u64),                // Vault ID -> VaultData
+    VaultShares(u64, Address), // (vault_id, user) -> shares
+    VaultDepositQueue(u64),    // Vault ID -> deposits waiting for the next epoch
+    VaultWithdrawalQueue(u64), // Vault ID -> share redemptions waiting for the next epoch
+    VaultEpochOptions(u64),    // Vault ID -> options sold in the current epoch
+
+    // Multi-leg strategies
+    StrategyCounter,
+    Strategy(u64),           // Strategy ID -> StrategyData
+    StrategyLeg(u64, u32),   // (Strategy ID, leg index) -> StrategyLegRecord
+    PoolOpenStrategies(u64), // Pool ID -> active strategies written against the pool
+
+    // Pool configuration
+    PoolParams(u64),        // Pool ID -> PoolParams in effect
+    PendingPoolParams(u64), // Pool ID -> PendingPoolParams waiting out the timelock
+    ProtocolFees(Address),  // Stable token -> protocol fees collected and not withdrawn
+    InsuranceFund(Address), // Stable token -> InsuranceFund backing its pools
+
+    // LP governance
+    ProposalCounter,
+    Proposal(u64),                    // Proposal ID -> Proposal
+    ProposalVote(u64, Address),       // (proposal_id, voter) -> weight cast
+    LpShareCheckpoints(u64, Address), // (pool_id, user) -> share balance history
+
+    // Pricing inputs
+    PoolVolSurface(u64), // Pool ID -> VolSurface, used instead of the flat volatility once set
+    PoolVolOracle(u64),  // Pool ID -> address allowed to update the vol surface
+
+    // RFQ quoters
+    PoolQuoters(u64),            // Pool ID -> registered ed25519 quoter keys
+    QuoteNonce(BytesN<32>, u64), // (quoter key, nonce) -> used
+
+    // Peer-to-peer option orders
+    OrderCounter,
+    OptionOrder(u64), // Order ID -> OptionOrder
+
+    // Perpetual options
+    PerpetualFunding(u64), // Option ID -> PerpetualFunding
 }
 
 #[contract]
@@ -43,31 +113,31 @@ pub struct MyPriceFeed;
 
 #[contractimpl]
 impl PriceFeedTrait for MyPriceFeed {
-    fn base(env: Env) -> Asset {
+    fn base(_env: Env) -> Asset {
         todo!()
     }
 
-    fn assets(env: Env) -> Vec<Asset> {
+    fn assets(_env: Env) -> Vec<Asset> {
         todo!()
     }
 
-    fn decimals(env: Env) -> u32 {
+    fn decimals(_env: Env) -> u32 {
         todo!()
     }
 
-    fn resolution(env: Env) -> u32 {
+    fn resolution(_env: Env) -> u32 {
         todo!()
     }
 
-    fn price(env: Env, asset: Asset, timestamp: u64) -> Option<PriceData> {
+    fn price(_env: Env, _asset: Asset, _timestamp: u64) -> Option<PriceData> {
         todo!()
     }
 
-    fn prices(env: Env, asset: Asset, records: u32) -> Option<Vec<PriceData>> {
+    fn prices(_env: Env, _asset: Asset, _records: u32) -> Option<Vec<PriceData>> {
         todo!()
     }
 
-    fn lastprice(env: Env, asset: Asset) -> Option<PriceData> {
+    fn lastprice(_env: Env, _asset: Asset) -> Option<PriceData> {
         todo!()
     }
     // impl the trait functions
@@ -83,6 +153,10 @@ pub struct PoolData {
     pub price_feed: Address,       // Price oracle for the underlying asset
     pub name: String,              // Human readable name like "BTC/USDC Options Pool"
     pub is_active: bool,           // Pool can be paused by admin
+    pub stable_decimals: u32,      // Read from stable_token when the pool is created
+    pub underlying_decimals: u32,  // Read from underlying_asset when the pool is created
+    pub winding_down: bool,        // No new options or deposits; positions run off
+    pub is_retired: bool,          // Fully drained and archived; its pair is free again
 }
 
 // Option types
@@ -93,6 +167,99 @@ pub enum OptionType {
     Put,
 }
 
+// How an exercised option is settled
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SettlementType {
+    Cash,     // Intrinsic value paid in stable_token
+    Physical, // Underlying exchanged against strike in stable_token
+}
+
+// Who wrote an option and backs its payoff
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OptionWriter {
+    Pool,
+    Vault(u64),       // Vault ID
+    Account(Address), // Peer-to-peer writer backing the option with its own collateral
+}
+
+// Exercise styles
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OptionStyle {
+    American, // Exercise anytime up to expiry
+    European, // Exercise only during the settlement window after expiry
+}
+
+// Side of spot the barrier sits on
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BarrierDirection {
+    Up,   // Crossed when the oracle price trades at or above the level
+    Down, // Crossed when the oracle price trades at or below the level
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BarrierKind {
+    KnockIn,  // Only exercisable once the barrier has been crossed
+    KnockOut, // Dies as soon as the barrier is crossed
+}
+
+// Barrier requested by a buyer
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BarrierTerms {
+    pub level: i128, // barrier price (scaled 1e7)
+    pub direction: BarrierDirection,
+    pub kind: BarrierKind,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Barrier {
+    pub level: i128, // barrier price (scaled 1e7)
+    pub direction: BarrierDirection,
+    pub kind: BarrierKind,
+    pub monitor_from: u64, // oracle history before this is already checked (from purchase)
+    pub is_crossed: bool,
+}
+
+// What an exercised option pays
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OptionPayoff {
+    Vanilla,                  // Intrinsic value
+    CashOrNothing(i128),      // Fixed stable payout per unit (scaled 1e7) if in the money at expiry
+    Average(AveragingParams), // Intrinsic value against the average oracle price before expiry
+}
+
+// Averaging window of an Asian option
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AveragingParams {
+    pub window: u64,      // seconds before expiry covered by the average
+    pub min_samples: u32, // oracle prints required in the window to settle
+}
+
+// Contract types can't hold Option<Barrier>, so vanilla options carry NoBarrier
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OptionBarrier {
+    NoBarrier,
+    Barrier(Barrier),
+}
+
+impl OptionBarrier {
+    pub fn get(&self) -> Option<Barrier> {
+        match self {
+            OptionBarrier::NoBarrier => None,
+            OptionBarrier::Barrier(barrier) => Some(barrier.clone()),
+        }
+    }
+}
+
 // Option struct - now includes pool_id
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -107,6 +274,297 @@ pub struct OptionData {
     pub collateral: i128,   // locked collateral
     pub is_exercised: bool,
     pub is_active: bool,
+    pub style: OptionStyle,
+    pub series_id: Option<u64>, // Listed series this option was bought from, if any
+    pub settlement: SettlementType,
+    pub writer: OptionWriter,
+    pub barrier: OptionBarrier,
+    pub payoff: OptionPayoff,
+}
+
+// Listed option series - standardized contracts that buyers trade by ID
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SeriesData {
+    pub series_id: u64,
+    pub pool_id: u64,
+    pub opt_type: OptionType,
+    pub strike: i128, // strike price (scaled 1e7)
+    pub expiry: u64,  // unix timestamp
+    pub style: OptionStyle,
+    pub open_interest: i128, // amount held in active options (scaled 1e7)
+    pub is_archived: bool,   // Set once the series has expired and is delisted
+}
+
+// Passive yield strategies that sell one OTM series per epoch
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VaultStrategy {
+    CoveredCall,    // Deposits underlying_asset, sells calls above spot
+    CashSecuredPut, // Deposits stable_token, sells puts below spot
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VaultData {
+    pub vault_id: u64,
+    pub pool_id: u64,
+    pub strategy: VaultStrategy,
+    pub strike_offset_bps: u32, // OTM distance from spot for each epoch's series
+    pub tick_size: i128,        // strikes are rounded to a multiple of this (scaled 1e7)
+    pub epoch: u32,
+    pub series_id: Option<u64>, // Series sold during the current epoch
+    pub total_assets: i128,     // deposit asset owned by share holders
+    pub total_proceeds: i128,   // other pool asset received from premiums and exercises
+    pub locked: i128,           // deposit asset locked as collateral this epoch
+    pub total_shares: i128,
+    pub pending_deposits: i128, // deposits waiting to be converted into shares
+}
+
+// Deposit (amount in tokens) or withdrawal (amount in shares) waiting for an epoch boundary
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QueuedRequest {
+    pub account: Address,
+    pub amount: i128,
+}
+
+// Queue status of a provider's pending pool withdrawals
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WithdrawalEstimate {
+    pub position: u32,       // index of the provider's first request in the queue
+    pub queued_shares: i128, // provider's shares across all queued requests
+    pub queued_value: i128,  // current stable value of those shares
+    pub value_ahead: i128,   // value of requests that will be filled first
+    pub fillable_now: i128,  // part of queued_value a process_withdrawals call would pay now
+}
+
+// Per-pool configuration for automatic series generation
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SeriesSchedule {
+    pub tick_size: i128, // strikes are rounded to a multiple of this (scaled 1e7)
+    pub strike_offsets_bps: Vec<u32>, // ladder around spot, e.g. [500, 1000, 2000] = ±5/10/20%
+    pub weekly_expiries: u32, // upcoming Friday 08:00 UTC expiries to list
+    pub monthly_expiries: u32, // upcoming last-Friday-of-month expiries to list
+    pub style: OptionStyle,
+}
+
+// Direction of a strategy leg from the trader's point of view
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PositionSide {
+    Long,  // Trader buys the option from the pool
+    Short, // Trader sells the option to the pool
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StrategyLeg {
+    pub opt_type: OptionType,
+    pub side: PositionSide,
+    pub strike: i128, // strike price (scaled 1e7)
+    pub expiry: u64,  // unix timestamp
+    pub amount: i128, // quantity (scaled 1e7)
+}
+
+// Multi-leg order priced and collateralized as one package
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StrategyData {
+    pub strategy_id: u64,
+    pub pool_id: u64,
+    pub owner: Address,
+    pub legs: Vec<StrategyLeg>, // terms as ordered; settlement is tracked per StrategyLegRecord
+    pub net_premium: i128,      // paid by the owner, negative for a net credit
+    pub pool_collateral: i128,  // pool's max loss on the combined payoff
+    pub owner_collateral: i128, // owner's max loss on the combined payoff
+    pub settled_payoff: i128,   // sum of the settled legs' payoffs
+    pub expiry: u64,            // latest leg expiry; the package pays out once all legs settle
+    pub is_active: bool,
+}
+
+// One leg of a strategy, settled on its own at its expiry price
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StrategyLegRecord {
+    pub strategy_id: u64,
+    pub leg: StrategyLeg,
+    pub premium: i128,          // model premium, negative when the pool paid it
+    pub settlement_price: i128, // 0 until settled, or if the settlement window lapsed
+    pub payoff: i128,           // owner's payoff, positive when the pool pays
+    pub is_settled: bool,
+}
+
+// Off-chain RFQ quote; the quoter signs the XDR of (contract address, quote)
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OptionQuote {
+    pub pool_id: u64,
+    pub opt_type: OptionType,
+    pub strike: i128,     // strike price (scaled 1e7)
+    pub expiry: u64,      // unix timestamp
+    pub amount: i128,     // quantity (scaled 1e7)
+    pub premium: i128,    // replaces the model premium
+    pub valid_until: u64, // unix timestamp
+    pub nonce: u64,       // single use per quoter
+}
+
+// Ask listed by a peer-to-peer writer who has escrowed the option's collateral
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OptionOrder {
+    pub order_id: u64,
+    pub pool_id: u64,
+    pub writer: Address,
+    pub opt_type: OptionType,
+    pub strike: i128,      // strike price (scaled 1e7)
+    pub expiry: u64,       // unix timestamp
+    pub amount: i128,      // quantity (scaled 1e7)
+    pub collateral: i128,  // stable escrowed by the writer
+    pub ask_premium: i128, // premium the buyer pays to the writer
+    pub is_open: bool,     // false once filled or cancelled
+}
+
+// Governed configuration of a pool. Changes go through a timelocked queue.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PoolParams {
+    pub price_feed: Address,      // SEP-40 oracle for the underlying
+    pub volatility: i128,         // flat annualized vol when no surface is set (scaled 1e7)
+    pub premium_bps: u32,         // vanilla model premium, share of strike notional
+    pub fee_bps: u32,             // protocol fee charged on top of premiums
+    pub max_utilization_bps: u32, // most of the pool's liquidity options may lock
+    pub min_expiry: u64,          // shortest time to expiry accepted (seconds)
+    pub max_expiry: u64,          // longest time to expiry accepted (seconds)
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PendingPoolParams {
+    pub params: PoolParams,
+    pub queued_at: u64,
+    pub eta: u64, // earliest time the change can be executed
+}
+
+// LP proposal to change a pool's parameters, weighted by shares held before it was made
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Proposal {
+    pub proposal_id: u64,
+    pub pool_id: u64,
+    pub proposer: Address,
+    pub params: PoolParams,
+    pub snapshot_ledger: u32, // votes count shares held before this ledger
+    pub total_shares: i128,   // votable pool LP shares when proposed; quorum is measured against it
+    pub votes_for: i128,
+    pub votes_against: i128,
+    pub voting_ends: u64,
+    pub eta: u64,        // earliest execution time if the vote passes
+    pub expires_at: u64, // a passed proposal not executed by then lapses
+    pub executed: bool,
+}
+
+// LP share balance from a ledger onwards
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ShareCheckpoint {
+    pub ledger: u32,
+    pub shares: i128,
+}
+
+// Implied volatility grid of a pool; strike moneyness (strike / spot) by time to expiry
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VolSurface {
+    pub moneyness: Vec<i128>, // ascending strike / spot points (scaled 1e7)
+    pub tenors: Vec<u64>,     // ascending times to expiry (seconds)
+    pub vols: Vec<i128>,      // annualized vols (scaled 1e7), one row of moneyness per tenor
+    pub updated_at: u64,      // ledger time of the last update
+}
+
+// Model sensitivities of an option position from the holder's side. Delta is in
+// underlying units and gamma in underlying units per 1.0 of spot (both scaled 1e7); price,
+// vega (per vol point) and theta (per day) are in stable token units.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OptionGreeks {
+    pub price: i128,
+    pub delta: i128,
+    pub gamma: i128,
+    pub vega: i128,
+    pub theta: i128,
+}
+
+// Aggregate exposure of a pool to the options it has written, from the pool's side
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PoolRisk {
+    pub spot: i128,
+    pub open_options: u32,
+    pub open_strategies: u32,
+    pub net_delta: i128,
+    pub net_gamma: i128,
+    pub total_vega: i128,
+    pub net_theta: i128,
+    pub shock_bps: u32,    // size of the spot moves below
+    pub payout_up: i128,   // paid if every option were exercised after spot rises shock_bps
+    pub payout_down: i128, // paid if every option were exercised after spot falls shock_bps
+}
+
+// Pool balance sheet with open options marked to model
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PoolMarkToMarket {
+    pub total_liquidity: i128, // stable booked to the pool, including premiums received
+    pub delivered_value: i128, // underlying taken in by physical puts, at the oracle price
+    pub option_liability: i128, // value of the open options and strategies to their holders
+    pub nav: i128,             // liquidity plus delivered value, less liability; prices shares
+    pub open_premiums: i128,   // premiums received for the positions still open
+    pub unrealized_pnl: i128,  // open_premiums net of option_liability
+    pub open_options: u32,
+    pub open_strategies: u32,
+}
+
+// Funding account of a perpetual option, drawn down by the pool as time passes
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PerpetualFunding {
+    pub deposit: i128,    // stable left to pay funding from
+    pub unpaid: i128,     // funding that accrued after the deposit ran out
+    pub total_paid: i128, // funding collected by the pool so far
+    pub accrued_at: u64,  // last time funding was charged
+}
+
+// Per stable token backstop for pools whose liquidity falls short of their obligations
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InsuranceFund {
+    pub balance: i128,
+    pub total_contributed: i128, // protocol fee slices and direct deposits
+    pub total_covered: i128,     // paid into pools to cover shortfalls
+}
+
+// Insurance fund balance against what the pools on its stable token have at risk
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InsuranceFundHealth {
+    pub balance: i128,
+    pub locked_collateral: i128, // collateral locked by every pool on the stable token
+    pub open_shortfall: i128,    // collateral those pools' liquidity no longer backs
+    pub coverage_bps: u32,       // balance over locked collateral, capped at 100%
+}
+
+// What buying a cash-settled option from one pool would cost and lock
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PoolQuote {
+    pub pool_id: u64,
+    pub premium: i128,    // model premium, booked to the pool
+    pub fee: i128,        // protocol fee charged on top of the premium
+    pub collateral: i128, // stable the option would lock
+    pub capacity: i128,   // stable the pool can still lock for new options
 }
 
 // Error types
@@ -122,13 +580,50 @@ pub enum OptionsError {
     OptionNotFound = 6,
     OptionNotActive = 7,
     OptionExpired = 8,
-    NotOptionOwner = 9,
+    // 9 (NotOptionOwner) is retired; it was never raised
     NotInTheMoney = 10,
     InsufficientShares = 11,
     PoolNotFound = 12,
     PoolNotActive = 13,
-    PoolAlreadyExists = 14,
+    // 14 (PoolAlreadyExists) is retired; deployed clients still decode it that way
     InvalidPrice = 15,
+    SeriesNotFound = 16,
+    SeriesAlreadyExists = 17,
+    SeriesArchived = 18,
+    NotExercisable = 19,
+    SeriesScheduleNotSet = 20,
+    InvalidSchedule = 21,
+    VaultNotFound = 22,
+    EpochInProgress = 23,
+    WithdrawalNotQueued = 24,
+    DepositTooSmall = 25,
+    SlippageExceeded = 26,
+    ArithmeticOverflow = 27,
+    TradeTooSmall = 28,
+    InvalidStrategy = 29,
+    OrderNotFound = 30,
+    OrderNotOpen = 31,
+    QuoterNotRegistered = 32,
+    QuoteExpired = 33,
+    QuoteNonceUsed = 34,
+    BarrierNotCrossed = 35,
+    InvalidBarrier = 36,
+    InvalidAveraging = 37,
+    NotPerpetual = 38,
+    FundingNotExhausted = 39,
+    InvalidVolSurface = 40,
+    VolSurfaceNotSet = 41,
+    InvalidPoolParams = 42,
+    ParamsChangePending = 43,
+    NoPendingParams = 44,
+    TimelockNotElapsed = 45,
+    ExpiryOutOfBounds = 46,
+    ProposalNotFound = 47,
+    VotingClosed = 48,
+    AlreadyVoted = 49,
+    ProposalNotPassed = 50,
+    PoolNotDrained = 51,
+    TooManyOpenOptions = 52,
 }
 
 impl From<OptionsError> for Error {
@@ -145,6 +640,122 @@ const OPTION_EXERCISED: Symbol = symbol_short!("opt_exerc");
 const OPTION_EXPIRED: Symbol = symbol_short!("opt_exp");
 const POOL_ADDED: Symbol = symbol_short!("pool_add");
 const POOL_STATUS_CHANGED: Symbol = symbol_short!("pool_stat");
+const POOL_WINDING_DOWN: Symbol = symbol_short!("pool_wind");
+const POOL_RETIRED: Symbol = symbol_short!("pool_ret");
+const SERIES_LISTED: Symbol = symbol_short!("ser_list");
+const SERIES_ARCHIVED: Symbol = symbol_short!("ser_arch");
+const UNDERLYING_PROVIDED: Symbol = symbol_short!("und_prov");
+const UNDERLYING_WITHDRAWN: Symbol = symbol_short!("und_with");
+const WITHDRAWAL_QUEUED: Symbol = symbol_short!("wd_queue");
+const WITHDRAWAL_FILLED: Symbol = symbol_short!("wd_fill");
+const VAULT_CREATED: Symbol = symbol_short!("vlt_new");
+const VAULT_DEPOSIT_QUEUED: Symbol = symbol_short!("vlt_dep");
+const VAULT_WITHDRAWAL_QUEUED: Symbol = symbol_short!("vlt_wreq");
+const VAULT_WITHDRAWN: Symbol = symbol_short!("vlt_with");
+const VAULT_ROLLED: Symbol = symbol_short!("vlt_roll");
+const STRATEGY_PURCHASED: Symbol = symbol_short!("strat_buy");
+const STRATEGY_SETTLED: Symbol = symbol_short!("strat_set");
+const STRATEGY_LEG_SETTLED: Symbol = symbol_short!("strat_leg");
+const ORDER_PLACED: Symbol = symbol_short!("ord_new");
+const ORDER_FILLED: Symbol = symbol_short!("ord_fill");
+const ORDER_CANCELLED: Symbol = symbol_short!("ord_cncl");
+const QUOTER_ADDED: Symbol = symbol_short!("qtr_add");
+const QUOTER_REMOVED: Symbol = symbol_short!("qtr_rm");
+const BARRIER_CROSSED: Symbol = symbol_short!("barrier");
+const FUNDING_DEPOSITED: Symbol = symbol_short!("fund_dep");
+const PERPETUAL_LIQUIDATED: Symbol = symbol_short!("perp_liq");
+const VOL_ORACLE_SET: Symbol = symbol_short!("vol_orcl");
+const VOL_SURFACE_UPDATED: Symbol = symbol_short!("vol_surf");
+const PARAMS_QUEUED: Symbol = symbol_short!("prm_queue");
+const PARAMS_EXECUTED: Symbol = symbol_short!("prm_exec");
+const PARAMS_CANCELLED: Symbol = symbol_short!("prm_cncl");
+const FEES_WITHDRAWN: Symbol = symbol_short!("fee_with");
+const INSURANCE_FUNDED: Symbol = symbol_short!("ins_fund");
+const SHORTFALL_COVERED: Symbol = symbol_short!("ins_cover");
+const LP_LOSS: Symbol = symbol_short!("lp_loss");
+const PROPOSAL_CREATED: Symbol = symbol_short!("prop_new");
+const VOTE_CAST: Symbol = symbol_short!("vote");
+const PROPOSAL_EXECUTED: Symbol = symbol_short!("prop_exec");
+
+// European options can be exercised for this long after expiry (seconds)
+const EUROPEAN_EXERCISE_WINDOW: u64 = 86400;
+
+// Shares permanently locked by a pool's first deposit so the share price can't be inflated
+// (0.0001 tokens, scaled 1e7 and converted to the deposited token's units)
+const DEAD_SHARES: i128 = 1000;
+
+// Smallest accepted LP deposit (0.01 tokens, scaled 1e7 and converted to token units)
+const MIN_DEPOSIT: i128 = 100_000;
+
+// Smallest option amount that can be bought (0.01 units, scaled 1e7)
+const MIN_OPTION_AMOUNT: i128 = 100_000;
+
+// Annualized volatility new pools price with (80%)
+const DEFAULT_VOLATILITY: i128 = 8_000_000;
+
+// Vanilla model premium new pools charge (2% of strike notional)
+const DEFAULT_PREMIUM_BPS: u32 = 200;
+
+// Highest protocol fee a pool can be configured with (10% of premium)
+const MAX_FEE_BPS: u32 = 1_000;
+
+// Share of each protocol fee paid into the stable token's insurance fund
+const INSURANCE_FEE_SHARE_BPS: i128 = 2_500;
+
+// Delay between queueing a pool parameter change and executing it (seconds)
+const PARAMS_TIMELOCK: u64 = 2 * 86400;
+
+// How long LP proposals are open for voting (seconds)
+const VOTING_PERIOD: u64 = 3 * 86400;
+
+// How long a passed proposal can be executed after its timelock (seconds)
+const PROPOSAL_EXECUTION_WINDOW: u64 = 3 * 86400;
+
+// Share of a pool's LP shares that must vote for a proposal (20%)
+const PROPOSAL_QUORUM_BPS: i128 = 2_000;
+
+// Largest spot move get_pool_risk applies in both directions (90%)
+const MAX_RISK_SHOCK_BPS: u32 = 9_000;
+
+// Bump sizes for finite-difference greeks: 1% of spot, one vol point and one day
+const GREEK_SPOT_BUMP_BPS: i128 = 100;
+const GREEK_VOL_BUMP: i128 = 100_000;
+const GREEK_TIME_STEP: u64 = 86400;
+
+// Sanity bounds on vol surface points (1% to 500%)
+const MIN_VOLATILITY: i128 = 100_000;
+const MAX_VOLATILITY: i128 = 50_000_000;
+
+// Most moneyness or tenor points in a vol surface
+const MAX_SURFACE_POINTS: u32 = 8;
+
+// Oldest print accepted as an expiry price (seconds before expiry)
+const MAX_EXPIRY_PRICE_AGE: u64 = 3600;
+
+// Most options a pool can have open; provide, withdraw and process_withdrawals reprice
+// all of them. Past half of it, each new option must lock at least an even share of the
+// capacity left per remaining slot, so the list only fills up along with the pool.
+const MAX_POOL_OPEN_OPTIONS: u32 = 100;
+
+// Most oracle resolution periods read when checking price history
+const MAX_PRICE_RECORDS: u32 = 200;
+
+// Most legs accepted in a single strategy order
+const MAX_STRATEGY_LEGS: u32 = 4;
+
+// Most strategies a pool can have open, bounded like its open options
+const MAX_POOL_OPEN_STRATEGIES: u32 = 25;
+
+// Bounds on a series schedule so one roll_series call stays within the budget:
+// at most (2 * offsets + 1) strikes x 2 types x expiries series per roll
+const MAX_SCHEDULE_OFFSETS: u32 = 4;
+const MAX_SCHEDULE_EXPIRIES: u32 = 4;
+
+// Expiry recorded for perpetual options, which never expire
+const PERPETUAL_EXPIRY: u64 = u64::MAX;
+
+// Perpetual holders pay the time value of an option this long-dated, per period (seconds)
+const FUNDING_PERIOD: u64 = 86400;
 
 #[contractimpl]
 impl OptionsContract {
@@ -160,6 +771,12 @@ impl OptionsContract {
         env.storage().instance().set(&DataKey::Admin, &admin);
         env.storage().instance().set(&DataKey::PoolCounter, &0u64);
         env.storage().instance().set(&DataKey::OptionCounter, &0u64);
+        env.storage().instance().set(&DataKey::SeriesCounter, &0u64);
+        env.storage().instance().set(&DataKey::VaultCounter, &0u64);
+        env.storage()
+            .instance()
+            .set(&DataKey::StrategyCounter, &0u64);
+        env.storage().instance().set(&DataKey::OrderCounter, &0u64);
 
         log!(&env, "Multi-pool options contract initialized");
     }
@@ -180,33 +797,49 @@ impl OptionsContract {
             .unwrap_or_else(|| panic_with_error!(&env, OptionsError::NotInitialized));
         admin.require_auth();
 
-        // Check if pool already exists for this pair
-        if env.storage().persistent().has(&DataKey::PoolExists(
-            stable_token.clone(),
-            underlying_asset.clone(),
-        )) {
-            panic_with_error!(&env, OptionsError::PoolAlreadyExists);
-        }
-
         let pool_id = Self::get_pool_counter(env.clone());
 
+        // Token precisions used to convert 1e7 prices and amounts into token units
+        let stable_decimals = TokenClient::new(&env, &stable_token).decimals();
+        let underlying_decimals = TokenClient::new(&env, &underlying_asset).decimals();
+
         // Create pool data
         let pool = PoolData {
             pool_id,
             stable_token: stable_token.clone(),
             underlying_asset: underlying_asset.clone(),
-            price_feed,
+            price_feed: price_feed.clone(),
             name: name.clone(),
             is_active: true,
+            stable_decimals,
+            underlying_decimals,
+            winding_down: false,
+            is_retired: false,
         };
 
         // Store pool data
         env.storage()
             .persistent()
             .set(&DataKey::Pool(pool_id), &pool);
+        // Pairs can have several pools, each with its own parameters
+        let mut pair_pools =
+            Self::get_pool_by_assets(env.clone(), stable_token.clone(), underlying_asset.clone());
+        pair_pools.push_back(pool_id);
+        env.storage().persistent().set(
+            &DataKey::PairPools(stable_token.clone(), underlying_asset.clone()),
+            &pair_pools,
+        );
         env.storage().persistent().set(
-            &DataKey::PoolExists(stable_token.clone(), underlying_asset.clone()),
-            &pool_id,
+            &DataKey::PoolParams(pool_id),
+            &PoolParams {
+                price_feed,
+                volatility: DEFAULT_VOLATILITY,
+                premium_bps: DEFAULT_PREMIUM_BPS,
+                fee_bps: 0,
+                max_utilization_bps: BPS as u32,
+                min_expiry: 0,
+                max_expiry: u64::MAX,
+            },
         );
 
         // Initialize pool financial data
@@ -245,6 +878,10 @@ impl OptionsContract {
         admin.require_auth();
 
         let mut pool = Self::get_pool(env.clone(), pool_id);
+        // Wind-down is one way
+        if pool.winding_down {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
         pool.is_active = is_active;
         env.storage()
             .persistent()
@@ -254,8 +891,75 @@ impl OptionsContract {
             .publish((POOL_STATUS_CHANGED, admin), (pool_id, is_active));
     }
 
+    /// Admin function to start closing a pool for good. No new options or deposits are
+    /// accepted; open options can still be exercised or expired and LPs can withdraw as
+    /// collateral unlocks.
+    pub fn wind_down_pool(env: Env, pool_id: u64) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        let mut pool = Self::get_pool(env.clone(), pool_id);
+        if pool.winding_down {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+        pool.is_active = false;
+        pool.winding_down = true;
+        env.storage()
+            .persistent()
+            .set(&DataKey::Pool(pool_id), &pool);
+
+        env.events().publish((POOL_WINDING_DOWN, admin), pool_id);
+    }
+
+    /// Admin function to archive a wound-down pool once every option has closed and only
+    /// the dead shares are left, removing it from its asset pair's pools
+    pub fn retire_pool(env: Env, pool_id: u64) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        let mut pool = Self::get_pool(env.clone(), pool_id);
+        let drained = pool.winding_down
+            && !pool.is_retired
+            && Self::get_pool_open_options(env.clone(), pool_id).is_empty()
+            && Self::get_pool_locked_collateral(env.clone(), pool_id) == 0
+            && Self::get_pool_locked_underlying(env.clone(), pool_id) == 0
+            && Self::get_pool_queued_shares(env.clone(), pool_id) == 0
+            && Self::get_pool_total_lp_shares(env.clone(), pool_id)
+                <= Self::token_units(&env, DEAD_SHARES, pool.stable_decimals)
+            && Self::get_pool_underlying_total_shares(env.clone(), pool_id)
+                <= Self::token_units(&env, DEAD_SHARES, pool.underlying_decimals);
+        if !drained {
+            panic_with_error!(&env, OptionsError::PoolNotDrained);
+        }
+
+        pool.is_retired = true;
+        env.storage()
+            .persistent()
+            .set(&DataKey::Pool(pool_id), &pool);
+        let mut pair_pools = Self::get_pool_by_assets(
+            env.clone(),
+            pool.stable_token.clone(),
+            pool.underlying_asset.clone(),
+        );
+        if let Some(index) = pair_pools.first_index_of(pool_id) {
+            pair_pools.remove(index);
+        }
+        env.storage().persistent().set(
+            &DataKey::PairPools(pool.stable_token, pool.underlying_asset),
+            &pair_pools,
+        );
+
+        env.events().publish((POOL_RETIRED, admin), pool_id);
+    }
+
     /// Provide liquidity to a specific pool
-    pub fn provide_liquidity(env: Env, pool_id: u64, provider: Address, amount: i128) -> i128 {
+    pub fn provide_liquidity(
+        env: Env,
+        pool_id: u64,
+        provider: Address,
+        amount: i128,
+        min_shares_out: i128,
+    ) -> i128 {
         provider.require_auth();
 
         if amount <= 0 {
@@ -270,32 +974,35 @@ impl OptionsContract {
         let total_liquidity = Self::get_pool_total_liquidity(env.clone(), pool_id);
         let total_lp_shares = Self::get_pool_total_lp_shares(env.clone(), pool_id);
 
+        // Shares are priced at NAV so new LPs buy into the open options' losses too
+        let nav = Self::pool_nav(&env, &pool);
+
+        // Calculate LP shares
+        let (shares, minted) = Self::deposit_shares(
+            &env,
+            amount,
+            nav,
+            total_lp_shares,
+            min_shares_out,
+            pool.stable_decimals,
+        );
+
         // Transfer tokens to contract
         let token_client = TokenClient::new(&env, &pool.stable_token);
         token_client.transfer(&provider, &env.current_contract_address(), &amount);
 
-        // Calculate LP shares
-        let shares = if total_lp_shares == 0 {
-            amount // 1:1 ratio for first provider
-        } else {
-            (amount * total_lp_shares) / total_liquidity
-        };
-
         // Update user shares
         let current_shares = Self::get_pool_lp_shares(env.clone(), pool_id, provider.clone());
-        env.storage().persistent().set(
-            &DataKey::PoolLpShares(pool_id, provider.clone()),
-            &(current_shares + shares),
-        );
+        Self::set_lp_shares(&env, pool_id, &provider, add(&env, current_shares, shares));
 
         // Update totals
         env.storage().persistent().set(
             &DataKey::PoolTotalLpShares(pool_id),
-            &(total_lp_shares + shares),
+            &add(&env, total_lp_shares, minted),
         );
         env.storage().persistent().set(
             &DataKey::PoolTotalLiquidity(pool_id),
-            &(total_liquidity + amount),
+            &add(&env, total_liquidity, amount),
         );
 
         // Emit event
@@ -315,44 +1022,32 @@ impl OptionsContract {
         provider.require_auth();
 
         let pool = Self::get_pool(env.clone(), pool_id);
-        if !pool.is_active {
+        if !pool.is_active && !pool.winding_down {
             panic_with_error!(&env, OptionsError::PoolNotActive);
         }
 
         let user_shares = Self::get_pool_lp_shares(env.clone(), pool_id, provider.clone());
-        if user_shares < share_amount {
+        if share_amount <= 0 || user_shares < share_amount {
             panic_with_error!(&env, OptionsError::InsufficientShares);
         }
 
-        let total_liquidity = Self::get_pool_total_liquidity(env.clone(), pool_id);
-        let locked_collateral = Self::get_pool_locked_collateral(env.clone(), pool_id);
+        // Shares would redeem for nothing while NAV isn't positive; LPs queue instead
+        let mtm = Self::mark_to_market(&env, &pool);
+        if mtm.nav <= 0 {
+            panic_with_error!(&env, OptionsError::InsufficientLiquidity);
+        }
+        let nav = Self::stable_nav(&mtm);
         let total_lp_shares = Self::get_pool_total_lp_shares(env.clone(), pool_id);
 
-        // Calculate withdrawal amount
-        let pool_portion = (share_amount * total_liquidity) / total_lp_shares;
-        let unlocked = total_liquidity - locked_collateral;
-
-        if pool_portion > unlocked {
+        // Calculate withdrawal amount at NAV; queued withdrawals are served first
+        let pool_portion = mul_div_floor(&env, share_amount, nav, total_lp_shares);
+        if pool_portion > Self::free_liquidity(&env, pool_id) {
             panic_with_error!(&env, OptionsError::InsufficientLiquidity);
         }
 
         // Update storage
-        env.storage().persistent().set(
-            &DataKey::PoolLpShares(pool_id, provider.clone()),
-            &(user_shares - share_amount),
-        );
-        env.storage().persistent().set(
-            &DataKey::PoolTotalLpShares(pool_id),
-            &(total_lp_shares - share_amount),
-        );
-        env.storage().persistent().set(
-            &DataKey::PoolTotalLiquidity(pool_id),
-            &(total_liquidity - pool_portion),
-        );
-
-        // Transfer tokens to provider
-        let token_client = TokenClient::new(&env, &pool.stable_token);
-        token_client.transfer(&env.current_contract_address(), &provider, &pool_portion);
+        Self::set_lp_shares(&env, pool_id, &provider, user_shares - share_amount);
+        Self::redeem_lp_shares(&env, &pool, &provider, share_amount, nav);
 
         // Emit event
         env.events().publish(
@@ -363,6 +1058,247 @@ impl OptionsContract {
         pool_portion
     }
 
+    /// Queue LP shares for withdrawal when there is not enough unlocked liquidity.
+    /// Queued shares are escrowed and filled FIFO by `process_withdrawals`.
+    pub fn request_withdrawal(env: Env, pool_id: u64, provider: Address, share_amount: i128) {
+        provider.require_auth();
+
+        // Queued shares are filled as winding-down pools unlock; retired pools have none
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if pool.is_retired {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+
+        let user_shares = Self::get_pool_lp_shares(env.clone(), pool_id, provider.clone());
+        if share_amount <= 0 || user_shares < share_amount {
+            panic_with_error!(&env, OptionsError::InsufficientShares);
+        }
+
+        Self::set_lp_shares(&env, pool_id, &provider, user_shares - share_amount);
+
+        let mut queue = Self::get_withdrawal_queue(env.clone(), pool_id);
+        queue.push_back(QueuedRequest {
+            account: provider.clone(),
+            amount: share_amount,
+        });
+        env.storage()
+            .persistent()
+            .set(&DataKey::PoolWithdrawalQueue(pool_id), &queue);
+
+        let queued = Self::get_pool_queued_shares(env.clone(), pool_id);
+        env.storage().persistent().set(
+            &DataKey::PoolQueuedShares(pool_id),
+            &add(&env, queued, share_amount),
+        );
+
+        env.events().publish(
+            (WITHDRAWAL_QUEUED, provider),
+            (pool_id, share_amount, queue.len() - 1),
+        );
+    }
+
+    /// Fill queued withdrawals FIFO from unlocked liquidity - callable by anyone.
+    /// The request at the head of the queue may be partially filled. Returns the amount paid.
+    pub fn process_withdrawals(env: Env, pool_id: u64) -> i128 {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        let queue = Self::get_withdrawal_queue(env.clone(), pool_id);
+
+        let mut remaining = Vec::new(&env);
+        let mut filled_shares = 0i128;
+        let mut paid = 0i128;
+
+        // Redemptions at NAV leave the share price unchanged, so it is marked once. A pool
+        // with no positive NAV would redeem shares for nothing, so the queue waits.
+        let mut nav = Self::stable_nav(&Self::mark_to_market(&env, &pool));
+        for request in queue.iter() {
+            let total_lp_shares = Self::get_pool_total_lp_shares(env.clone(), pool_id);
+            let available = Self::unlocked_liquidity(&env, pool_id);
+
+            let fillable = if nav <= 0 {
+                0
+            } else {
+                request
+                    .amount
+                    .min(mul_div_floor(&env, available, total_lp_shares, nav))
+            };
+            if fillable <= 0 {
+                remaining.push_back(request);
+                continue;
+            }
+
+            let amount = Self::redeem_lp_shares(&env, &pool, &request.account, fillable, nav);
+            filled_shares = add(&env, filled_shares, fillable);
+            paid = add(&env, paid, amount);
+            nav = sub(&env, nav, amount);
+
+            env.events().publish(
+                (WITHDRAWAL_FILLED, request.account.clone()),
+                (pool_id, fillable, amount),
+            );
+
+            if fillable < request.amount {
+                remaining.push_back(QueuedRequest {
+                    account: request.account,
+                    amount: request.amount - fillable,
+                });
+            }
+        }
+
+        env.storage()
+            .persistent()
+            .set(&DataKey::PoolWithdrawalQueue(pool_id), &remaining);
+        let queued = Self::get_pool_queued_shares(env.clone(), pool_id);
+        env.storage().persistent().set(
+            &DataKey::PoolQueuedShares(pool_id),
+            &sub(&env, queued, filled_shares),
+        );
+
+        paid
+    }
+
+    /// Provide underlying_asset inventory to back physically settled calls
+    pub fn provide_underlying_liquidity(
+        env: Env,
+        pool_id: u64,
+        provider: Address,
+        amount: i128,
+        min_shares_out: i128,
+    ) -> i128 {
+        provider.require_auth();
+
+        if amount <= 0 {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if !pool.is_active {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+
+        let total_underlying = Self::get_pool_underlying_liquidity(env.clone(), pool_id);
+        let total_shares = Self::get_pool_underlying_total_shares(env.clone(), pool_id);
+
+        // Shares also claim the strike proceeds of exercised calls, valued in underlying
+        // at the oracle price so new LPs don't buy into them at par
+        let proceeds = Self::get_pool_underlying_proceeds(env.clone(), pool_id);
+        let mut pool_value = total_underlying;
+        if proceeds > 0 {
+            let spot = Self::get_price_from_feed(env.clone(), pool.price_feed.clone());
+            pool_value = add(
+                &env,
+                pool_value,
+                convert_ceil(
+                    &env,
+                    proceeds,
+                    SCALE,
+                    spot,
+                    pool.stable_decimals,
+                    pool.underlying_decimals,
+                ),
+            );
+        }
+
+        let (shares, minted) = Self::deposit_shares(
+            &env,
+            amount,
+            pool_value,
+            total_shares,
+            min_shares_out,
+            pool.underlying_decimals,
+        );
+
+        let underlying_client = TokenClient::new(&env, &pool.underlying_asset);
+        underlying_client.transfer(&provider, &env.current_contract_address(), &amount);
+
+        let current_shares =
+            Self::get_pool_underlying_lp_shares(env.clone(), pool_id, provider.clone());
+        env.storage().persistent().set(
+            &DataKey::PoolUnderlyingLpShares(pool_id, provider.clone()),
+            &add(&env, current_shares, shares),
+        );
+        env.storage().persistent().set(
+            &DataKey::PoolUnderlyingTotalShares(pool_id),
+            &add(&env, total_shares, minted),
+        );
+        env.storage().persistent().set(
+            &DataKey::PoolUnderlyingLiquidity(pool_id),
+            &add(&env, total_underlying, amount),
+        );
+
+        env.events()
+            .publish((UNDERLYING_PROVIDED, provider), (pool_id, amount, shares));
+
+        shares
+    }
+
+    /// Withdraw underlying inventory plus a pro-rata share of strike proceeds
+    pub fn withdraw_underlying_liquidity(
+        env: Env,
+        pool_id: u64,
+        provider: Address,
+        share_amount: i128,
+    ) -> i128 {
+        provider.require_auth();
+
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if !pool.is_active && !pool.winding_down {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+
+        let user_shares =
+            Self::get_pool_underlying_lp_shares(env.clone(), pool_id, provider.clone());
+        if share_amount <= 0 || user_shares < share_amount {
+            panic_with_error!(&env, OptionsError::InsufficientShares);
+        }
+
+        let total_underlying = Self::get_pool_underlying_liquidity(env.clone(), pool_id);
+        let locked_underlying = Self::get_pool_locked_underlying(env.clone(), pool_id);
+        let proceeds = Self::get_pool_underlying_proceeds(env.clone(), pool_id);
+        let total_shares = Self::get_pool_underlying_total_shares(env.clone(), pool_id);
+
+        let underlying_portion = mul_div_floor(&env, share_amount, total_underlying, total_shares);
+        let stable_portion = mul_div_floor(&env, share_amount, proceeds, total_shares);
+
+        if underlying_portion > total_underlying - locked_underlying {
+            panic_with_error!(&env, OptionsError::InsufficientLiquidity);
+        }
+
+        env.storage().persistent().set(
+            &DataKey::PoolUnderlyingLpShares(pool_id, provider.clone()),
+            &sub(&env, user_shares, share_amount),
+        );
+        env.storage().persistent().set(
+            &DataKey::PoolUnderlyingTotalShares(pool_id),
+            &sub(&env, total_shares, share_amount),
+        );
+        env.storage().persistent().set(
+            &DataKey::PoolUnderlyingLiquidity(pool_id),
+            &sub(&env, total_underlying, underlying_portion),
+        );
+        env.storage().persistent().set(
+            &DataKey::PoolUnderlyingProceeds(pool_id),
+            &sub(&env, proceeds, stable_portion),
+        );
+
+        let underlying_client = TokenClient::new(&env, &pool.underlying_asset);
+        underlying_client.transfer(
+            &env.current_contract_address(),
+            &provider,
+            &underlying_portion,
+        );
+        if stable_portion > 0 {
+            let token_client = TokenClient::new(&env, &pool.stable_token);
+            token_client.transfer(&env.current_contract_address(), &provider, &stable_portion);
+        }
+
+        env.events().publish(
+            (UNDERLYING_WITHDRAWN, provider),
+            (pool_id, share_amount, underlying_portion, stable_portion),
+        );
+
+        underlying_portion
+    }
+
     /// Buy an option from a specific pool
     pub fn buy_option(
         env: Env,
@@ -375,325 +1311,4177 @@ impl OptionsContract {
     ) -> u64 {
         buyer.require_auth();
 
-        let pool = Self::get_pool(env.clone(), pool_id);
-        if !pool.is_active {
-            panic_with_error!(&env, OptionsError::PoolNotActive);
+        Self::open_option(
+            &env,
+            pool_id,
+            buyer,
+            opt_type,
+            strike,
+            expiry,
+            amount,
+            OptionStyle::American,
+            None,
+            SettlementType::Cash,
+            None,
+        )
+    }
+
+    /// Buy a cash-settled option from whichever pool for the pair quotes it cheapest
+    /// (premium plus fee) and can lock its collateral. When no single pool can and the
+    /// order allows splitting, it is filled across pools from the cheapest unit price up.
+    /// `max_premium` caps the premiums plus fees over every fill. Returns the IDs of the
+    /// options bought.
+    pub fn buy_option_best(
+        env: Env,
+        stable_token: Address,
+        underlying_asset: Address,
+        buyer: Address,
+        opt_type: OptionType,
+        strike: i128,
+        expiry: u64,
+        amount: i128,
+        max_premium: i128,
+        allow_split: bool,
+    ) -> Vec<u64> {
+        buyer.require_auth();
+
+        let quotes = Self::get_pair_quotes(
+            env.clone(),
+            stable_token,
+            underlying_asset,
+            opt_type.clone(),
+            strike,
+            expiry,
+            amount,
+        );
+        let fills = Self::route_fills(&env, &quotes, amount, allow_split);
+
+        // Price every fill before buying so the cap covers the whole order
+        let mut total_cost = 0i128;
+        for (pool_id, fill_amount) in fills.iter() {
+            let pool = Self::get_pool(env.clone(), pool_id);
+            let premium =
+                Self::model_premium(&env, &pool, &opt_type, strike, expiry, fill_amount, true);
+            total_cost = add(
+                &env,
+                total_cost,
+                add(&env, premium, Self::protocol_fee(&env, pool_id, premium)),
+            );
+        }
+        if total_cost > max_premium {
+            panic_with_error!(&env, OptionsError::SlippageExceeded);
         }
 
-        if expiry <= env.ledger().timestamp() {
-            panic_with_error!(&env, OptionsError::OptionExpired);
+        let mut option_ids = Vec::new(&env);
+        for (pool_id, fill_amount) in fills.iter() {
+            option_ids.push_back(Self::open_option(
+                &env,
+                pool_id,
+                buyer.clone(),
+                opt_type.clone(),
+                strike,
+                expiry,
+                fill_amount,
+                OptionStyle::American,
+                None,
+                SettlementType::Cash,
+                None,
+            ));
         }
+        option_ids
+    }
+
+    /// Buy a physically settled option: calls are backed by the pool's underlying
+    /// inventory, puts by stable liquidity
+    pub fn buy_physical_option(
+        env: Env,
+        pool_id: u64,
+        buyer: Address,
+        opt_type: OptionType,
+        strike: i128,
+        expiry: u64,
+        amount: i128,
+    ) -> u64 {
+        buyer.require_auth();
+
+        Self::open_option(
+            &env,
+            pool_id,
+            buyer,
+            opt_type,
+            strike,
+            expiry,
+            amount,
+            OptionStyle::American,
+            None,
+            SettlementType::Physical,
+            None,
+        )
+    }
+
+    /// Buy a cash-settled knock-in or knock-out option. The barrier is monitored on the
+    /// pool oracle's price history from the time of purchase, and the premium is the
+    /// barrier option's Black-Scholes value.
+    pub fn buy_barrier_option(
+        env: Env,
+        pool_id: u64,
+        buyer: Address,
+        opt_type: OptionType,
+        strike: i128,
+        expiry: u64,
+        amount: i128,
+        terms: BarrierTerms,
+    ) -> u64 {
+        buyer.require_auth();
 
+        // The barrier must not already be crossed at purchase
+        let pool = Self::get_pool(env.clone(), pool_id);
+        let spot = Self::get_price_from_feed(env.clone(), pool.price_feed.clone());
+        let valid = match terms.direction {
+            BarrierDirection::Up => terms.level > spot,
+            BarrierDirection::Down => terms.level < spot && terms.level > 0,
+        };
+        if !valid {
+            panic_with_error!(&env, OptionsError::InvalidBarrier);
+        }
+        Self::require_expiry_in_bounds(&env, pool_id, expiry);
         if amount <= 0 || strike <= 0 {
             panic_with_error!(&env, OptionsError::InvalidAmount);
         }
 
-        // Calculate premium (2% of strike * amount, normalized for 1e7 scaling)
-        let normalized_amount = amount / 10_000_000;
-        let normalized_amount = if normalized_amount == 0 {
-            1
-        } else {
-            normalized_amount
+        let now = env.ledger().timestamp();
+        let unit_price = pricing::barrier_price(
+            &env,
+            spot,
+            strike,
+            terms.level,
+            Self::model_volatility(&env, pool_id, spot, strike, expiry - now),
+            pricing::years_until(&env, now, expiry),
+            opt_type == OptionType::Call,
+            terms.direction == BarrierDirection::Up,
+            terms.kind == BarrierKind::KnockIn,
+        );
+        let premium = value_ceil(&env, unit_price, amount, pool.stable_decimals);
+
+        let option_id = Self::open_option(
+            &env,
+            pool_id,
+            buyer,
+            opt_type,
+            strike,
+            expiry,
+            amount,
+            OptionStyle::American,
+            None,
+            SettlementType::Cash,
+            Some(premium),
+        );
+
+        let mut option = Self::get_option(env.clone(), option_id);
+        option.barrier = OptionBarrier::Barrier(Barrier {
+            level: terms.level,
+            direction: terms.direction,
+            kind: terms.kind,
+            monitor_from: env.ledger().timestamp(),
+            is_crossed: false,
+        });
+        env.storage()
+            .persistent()
+            .set(&DataKey::Option(option_id), &option);
+
+        option_id
+    }
+
+    /// Check the oracle's price history since purchase and apply a crossed barrier:
+    /// knock-out options close and release their collateral, knock-in options become
+    /// exercisable. Anyone can call this. History is read a page at a time; a call that
+    /// finds no crossing in a page short of the present saves its progress and returns
+    /// false, so it can be called again to continue.
+    pub fn trigger_barrier(env: Env, option_id: u64) -> bool {
+        let mut option = Self::get_option(env.clone(), option_id);
+        if !option.is_active {
+            panic_with_error!(&env, OptionsError::OptionNotActive);
+        }
+        let barrier = match option.barrier.get() {
+            Some(barrier) if !barrier.is_crossed => barrier,
+            _ => panic_with_error!(&env, OptionsError::InvalidBarrier),
         };
-        let premium = (strike * normalized_amount * 200) / 10000; // 2%
 
-        // Calculate required collateral
-        let collateral_needed = strike * amount / 10_000_000;
+        let pool = Self::get_pool(env.clone(), option.pool_id);
+        let (crossing, scanned_to) = Self::find_barrier_crossing(&env, &pool, &option, &barrier);
+        let crossing = match crossing {
+            Some(crossing) => crossing,
+            None if scanned_to < env.ledger().timestamp().min(option.expiry) => {
+                option.barrier = OptionBarrier::Barrier(Barrier {
+                    monitor_from: scanned_to + 1,
+                    ..barrier
+                });
+                env.storage()
+                    .persistent()
+                    .set(&DataKey::Option(option_id), &option);
+                return false;
+            }
+            None => panic_with_error!(&env, OptionsError::BarrierNotCrossed),
+        };
 
-        // Check available liquidity in this pool
-        let total_liquidity = Self::get_pool_total_liquidity(env.clone(), pool_id);
-        let locked_collateral = Self::get_pool_locked_collateral(env.clone(), pool_id);
-        let unlocked = total_liquidity - locked_collateral;
+        match barrier.kind {
+            BarrierKind::KnockOut => Self::knock_out(&env, option_id, option, crossing),
+            BarrierKind::KnockIn => {
+                option.barrier = OptionBarrier::Barrier(Barrier {
+                    is_crossed: true,
+                    ..barrier
+                });
+                env.storage()
+                    .persistent()
+                    .set(&DataKey::Option(option_id), &option);
 
-        if unlocked < collateral_needed {
+                env.events().publish(
+                    (BARRIER_CROSSED, option.buyer),
+                    (
+                        option_id,
+                        BarrierKind::KnockIn,
+                        crossing.price,
+                        crossing.timestamp,
+                    ),
+                );
+            }
+        }
+        true
+    }
+
+    /// Buy a European cash-or-nothing option paying `payout` per unit if spot is above
+    /// (call) or below (put) the strike at expiry. The full payout is locked as
+    /// collateral and the premium is the digital Black-Scholes price.
+    pub fn buy_digital_option(
+        env: Env,
+        pool_id: u64,
+        buyer: Address,
+        opt_type: OptionType,
+        strike: i128,
+        expiry: u64,
+        amount: i128,
+        payout: i128,
+    ) -> u64 {
+        buyer.require_auth();
+
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if !pool.is_active {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+        let now = env.ledger().timestamp();
+        Self::require_expiry_in_bounds(&env, pool_id, expiry);
+        if amount <= 0 || strike <= 0 || payout <= 0 {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+        Self::require_min_trade(&env, amount);
+
+        let collateral = value_ceil(&env, payout, amount, pool.stable_decimals);
+        if Self::option_capacity(&env, pool_id) < collateral {
             panic_with_error!(&env, OptionsError::InsufficientLiquidity);
         }
 
-        // Transfer premium from buyer
-        let token_client = TokenClient::new(&env, &pool.stable_token);
-        token_client.transfer(&buyer, &env.current_contract_address(), &premium);
+        let spot = Self::get_price_from_feed(env.clone(), pool.price_feed.clone());
+        let probability = pricing::digital_price(
+            &env,
+            spot,
+            strike,
+            Self::model_volatility(&env, pool_id, spot, strike, expiry - now),
+            pricing::years_until(&env, now, expiry),
+            opt_type == OptionType::Call,
+        );
+        let premium = mul_div_ceil(&env, collateral, probability, SCALE);
 
-        // Update locked collateral for this pool
+        Self::collect_premium(&env, &pool, &buyer, premium);
+
+        let locked_collateral = Self::get_pool_locked_collateral(env.clone(), pool_id);
         env.storage().persistent().set(
             &DataKey::PoolLockedCollateral(pool_id),
-            &(locked_collateral + collateral_needed),
+            &add(&env, locked_collateral, collateral),
         );
 
-        // Create option
-        let option_id = Self::get_option_counter(env.clone());
-        let option = OptionData {
-            pool_id,
-            buyer: buyer.clone(),
-            opt_type: opt_type.clone(),
-            strike,
-            expiry,
-            amount,
-            premium_paid: premium,
-            collateral: collateral_needed,
-            is_exercised: false,
-            is_active: true,
-        };
+        Self::store_option(
+            &env,
+            OptionData {
+                pool_id,
+                buyer,
+                opt_type,
+                strike,
+                expiry,
+                amount,
+                premium_paid: premium,
+                collateral,
+                is_exercised: false,
+                is_active: true,
+                style: OptionStyle::European,
+                series_id: None,
+                settlement: SettlementType::Cash,
+                writer: OptionWriter::Pool,
+                barrier: OptionBarrier::NoBarrier,
+                payoff: OptionPayoff::CashOrNothing(payout),
+            },
+        )
+    }
+
+    /// Buy a European average-price (Asian) option settled against the mean of the pool
+    /// oracle's prints over the last `averaging.window` seconds before expiry. Priced with
+    /// a moment-matched lognormal approximation of the arithmetic average.
+    pub fn buy_asian_option(
+        env: Env,
+        pool_id: u64,
+        buyer: Address,
+        opt_type: OptionType,
+        strike: i128,
+        expiry: u64,
+        amount: i128,
+        averaging: AveragingParams,
+    ) -> u64 {
+        buyer.require_auth();
+
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if !pool.is_active {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+        let now = env.ledger().timestamp();
+        Self::require_expiry_in_bounds(&env, pool_id, expiry);
+        if amount <= 0 || strike <= 0 {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+        Self::require_min_trade(&env, amount);
+
+        // The whole window must still be ahead so no part of the average is already known,
+        // and short enough for settlement to read every period of it in one call
+        let resolution = PriceFeedClient::new(&env, &pool.price_feed)
+            .resolution()
+            .max(1) as u64;
+        if averaging.window == 0
+            || averaging.window > expiry - now
+            || averaging.window > (MAX_PRICE_RECORDS as u64 - 1) * resolution
+            || averaging.min_samples == 0
+        {
+            panic_with_error!(&env, OptionsError::InvalidAveraging);
+        }
+
+        // Averages can't exceed the strike-sized collateral vanilla options lock
+        let collateral = value_ceil(&env, strike, amount, pool.stable_decimals);
+        if Self::option_capacity(&env, pool_id) < collateral {
+            panic_with_error!(&env, OptionsError::InsufficientLiquidity);
+        }
+
+        let spot = Self::get_price_from_feed(env.clone(), pool.price_feed.clone());
+        let unit_price = pricing::asian_price(
+            &env,
+            spot,
+            strike,
+            Self::model_volatility(&env, pool_id, spot, strike, expiry - now),
+            pricing::years_until(&env, now, expiry),
+            pricing::years_until(&env, expiry - averaging.window, expiry),
+            opt_type == OptionType::Call,
+        );
+        let premium = value_ceil(&env, unit_price, amount, pool.stable_decimals);
+
+        Self::collect_premium(&env, &pool, &buyer, premium);
+
+        let locked_collateral = Self::get_pool_locked_collateral(env.clone(), pool_id);
+        env.storage().persistent().set(
+            &DataKey::PoolLockedCollateral(pool_id),
+            &add(&env, locked_collateral, collateral),
+        );
+
+        Self::store_option(
+            &env,
+            OptionData {
+                pool_id,
+                buyer,
+                opt_type,
+                strike,
+                expiry,
+                amount,
+                premium_paid: premium,
+                collateral,
+                is_exercised: false,
+                is_active: true,
+                style: OptionStyle::European,
+                series_id: None,
+                settlement: SettlementType::Cash,
+                writer: OptionWriter::Pool,
+                barrier: OptionBarrier::NoBarrier,
+                payoff: OptionPayoff::Average(averaging),
+            },
+        )
+    }
+
+    /// Buy a perpetual cash-settled option. It never expires and can be exercised at any
+    /// time; instead of an upfront premium the holder streams funding to the pool out of
+    /// `funding_deposit`, which must cover at least one funding period at purchase.
+    pub fn buy_perpetual_option(
+        env: Env,
+        pool_id: u64,
+        buyer: Address,
+        opt_type: OptionType,
+        strike: i128,
+        amount: i128,
+        funding_deposit: i128,
+    ) -> u64 {
+        buyer.require_auth();
+
+        let option_id = Self::open_option(
+            &env,
+            pool_id,
+            buyer.clone(),
+            opt_type,
+            strike,
+            PERPETUAL_EXPIRY,
+            amount,
+            OptionStyle::American,
+            None,
+            SettlementType::Cash,
+            Some(0),
+        );
+
+        let option = Self::get_option(env.clone(), option_id);
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if funding_deposit < Self::funding_due(&env, &pool, &option, FUNDING_PERIOD) {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+
+        let token_client = TokenClient::new(&env, &pool.stable_token);
+        token_client.transfer(&buyer, &env.current_contract_address(), &funding_deposit);
+
+        env.storage().persistent().set(
+            &DataKey::PerpetualFunding(option_id),
+            &PerpetualFunding {
+                deposit: funding_deposit,
+                unpaid: 0,
+                total_paid: 0,
+                accrued_at: env.ledger().timestamp(),
+            },
+        );
+
+        env.events()
+            .publish((FUNDING_DEPOSITED, buyer), (option_id, funding_deposit));
+
+        option_id
+    }
+
+    /// Add to a perpetual option's funding deposit. Funding left unpaid since the deposit
+    /// ran out is settled from the top-up first.
+    pub fn top_up_funding(env: Env, option_id: u64, amount: i128) -> PerpetualFunding {
+        let option = Self::get_option(env.clone(), option_id);
+        option.buyer.require_auth();
+
+        if amount <= 0 {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+
+        let pool = Self::get_pool(env.clone(), option.pool_id);
+        let mut funding = Self::accrue_funding(&env, option_id, &option, &pool);
+
+        let token_client = TokenClient::new(&env, &pool.stable_token);
+        token_client.transfer(&option.buyer, &env.current_contract_address(), &amount);
+
+        let settled = amount.min(funding.unpaid);
+        funding.unpaid = sub(&env, funding.unpaid, settled);
+        funding.total_paid = add(&env, funding.total_paid, settled);
+        funding.deposit = add(&env, funding.deposit, sub(&env, amount, settled));
+        env.storage()
+            .persistent()
+            .set(&DataKey::PerpetualFunding(option_id), &funding);
+        Self::adjust_pool_liquidity(&env, option.pool_id, settled);
+
+        env.events()
+            .publish((FUNDING_DEPOSITED, option.buyer), (option_id, amount));
+
+        funding
+    }
+
+    /// Charge a perpetual option the funding accrued since it was last touched.
+    /// Callable by anyone, e.g. keepers checking positions for liquidation.
+    pub fn collect_funding(env: Env, option_id: u64) -> PerpetualFunding {
+        let option = Self::get_option(env.clone(), option_id);
+        let pool = Self::get_pool(env.clone(), option.pool_id);
+        Self::accrue_funding(&env, option_id, &option, &pool)
+    }
+
+    /// Keeper function to close a perpetual option whose funding deposit has run out.
+    /// The position is exercised at spot with the unpaid funding taken from its payoff.
+    pub fn liquidate_perpetual(env: Env, option_id: u64) -> i128 {
+        let option = Self::get_option(env.clone(), option_id);
+        let pool = Self::get_pool(env.clone(), option.pool_id);
+
+        let funding = Self::accrue_funding(&env, option_id, &option, &pool);
+        if funding.deposit > 0 {
+            panic_with_error!(&env, OptionsError::FundingNotExhausted);
+        }
+
+        let payout = Self::settle_exercise(&env, option_id, option.clone());
+        env.events()
+            .publish((PERPETUAL_LIQUIDATED, option.buyer), (option_id, payout));
+        payout
+    }
+
+    /// Buy an option at a premium quoted and signed off-chain by one of the pool's
+    /// registered quoters. Each quote nonce can only be used once.
+    pub fn buy_option_with_quote(
+        env: Env,
+        buyer: Address,
+        quote: OptionQuote,
+        quoter: BytesN<32>,
+        signature: BytesN<64>,
+    ) -> u64 {
+        buyer.require_auth();
+
+        if !Self::get_pool_quoters(env.clone(), quote.pool_id).contains(&quoter) {
+            panic_with_error!(&env, OptionsError::QuoterNotRegistered);
+        }
+        if env.ledger().timestamp() > quote.valid_until {
+            panic_with_error!(&env, OptionsError::QuoteExpired);
+        }
+        if quote.premium < 0 {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+
+        let nonce_key = DataKey::QuoteNonce(quoter.clone(), quote.nonce);
+        if env.storage().persistent().has(&nonce_key) {
+            panic_with_error!(&env, OptionsError::QuoteNonceUsed);
+        }
+
+        // Binding the contract address keeps quotes from being replayed elsewhere
+        let message = (env.current_contract_address(), quote.clone()).to_xdr(&env);
+        env.crypto().ed25519_verify(&quoter, &message, &signature);
+        env.storage().persistent().set(&nonce_key, &true);
+
+        Self::open_option(
+            &env,
+            quote.pool_id,
+            buyer,
+            quote.opt_type,
+            quote.strike,
+            quote.expiry,
+            quote.amount,
+            OptionStyle::American,
+            None,
+            SettlementType::Cash,
+            Some(quote.premium),
+        )
+    }
+
+    /// Admin function to register an ed25519 key allowed to quote on a pool
+    pub fn add_pool_quoter(env: Env, pool_id: u64, quoter: BytesN<32>) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        Self::get_pool(env.clone(), pool_id);
+        let mut quoters = Self::get_pool_quoters(env.clone(), pool_id);
+        if !quoters.contains(&quoter) {
+            quoters.push_back(quoter.clone());
+            env.storage()
+                .persistent()
+                .set(&DataKey::PoolQuoters(pool_id), &quoters);
+        }
+
+        env.events().publish((QUOTER_ADDED, pool_id), quoter);
+    }
+
+    /// Admin function to revoke a pool quoter
+    pub fn remove_pool_quoter(env: Env, pool_id: u64, quoter: BytesN<32>) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        let mut quoters = Self::get_pool_quoters(env.clone(), pool_id);
+        let index = quoters
+            .first_index_of(&quoter)
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::QuoterNotRegistered));
+        quoters.remove(index);
+        env.storage()
+            .persistent()
+            .set(&DataKey::PoolQuoters(pool_id), &quoters);
+
+        env.events().publish((QUOTER_REMOVED, pool_id), quoter);
+    }
+
+    /// Admin function to list a standardized option series on a pool
+    pub fn list_series(
+        env: Env,
+        pool_id: u64,
+        opt_type: OptionType,
+        strike: i128,
+        expiry: u64,
+        style: OptionStyle,
+    ) -> u64 {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if !pool.is_active {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+
+        Self::archive_series(&env, pool_id);
+        Self::create_series(&env, pool_id, opt_type, strike, expiry, style)
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::SeriesAlreadyExists))
+    }
+
+    /// Buy an option from a listed series
+    pub fn buy_series_option(env: Env, series_id: u64, buyer: Address, amount: i128) -> u64 {
+        buyer.require_auth();
+
+        let series = Self::get_series(env.clone(), series_id);
+        if series.is_archived {
+            panic_with_error!(&env, OptionsError::SeriesArchived);
+        }
+
+        Self::archive_series(&env, series.pool_id);
+        let option_id = Self::open_option(
+            &env,
+            series.pool_id,
+            buyer,
+            series.opt_type,
+            series.strike,
+            series.expiry,
+            amount,
+            series.style,
+            Some(series_id),
+            SettlementType::Cash,
+            None,
+        );
+
+        Self::update_open_interest(&env, series_id, amount);
+        option_id
+    }
+
+    /// Admin function to configure automatic series generation for a pool
+    pub fn set_series_schedule(env: Env, pool_id: u64, schedule: SeriesSchedule) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        Self::get_pool(env.clone(), pool_id);
+
+        if schedule.tick_size <= 0
+            || schedule.strike_offsets_bps.len() > MAX_SCHEDULE_OFFSETS
+            || schedule
+                .weekly_expiries
+                .saturating_add(schedule.monthly_expiries)
+                > MAX_SCHEDULE_EXPIRIES
+        {
+            panic_with_error!(&env, OptionsError::InvalidSchedule);
+        }
+        for offset in schedule.strike_offsets_bps.iter() {
+            if offset >= BPS as u32 {
+                panic_with_error!(&env, OptionsError::InvalidSchedule);
+            }
+        }
+
+        env.storage()
+            .persistent()
+            .set(&DataKey::SeriesSchedule(pool_id), &schedule);
+    }
+
+    /// List the pool's strike ladder around spot for the next scheduled expiries.
+    /// Keeper-callable; series that already exist are skipped.
+    pub fn roll_series(env: Env, pool_id: u64) -> Vec<u64> {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if !pool.is_active {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+
+        let schedule = Self::get_series_schedule(env.clone(), pool_id);
+        let spot = Self::get_price_from_feed(env.clone(), pool.price_feed);
+        let now = env.ledger().timestamp();
+
+        Self::archive_series(&env, pool_id);
+
+        // Strike ladder: spot itself plus each offset above and below, rounded to tick
+        let mut strikes = Vec::new(&env);
+        strikes.push_back(spot);
+        for offset in schedule.strike_offsets_bps.iter() {
+            let delta = mul_div_floor(&env, spot, offset as i128, BPS);
+            strikes.push_back(add(&env, spot, delta));
+            strikes.push_back(spot - delta);
+        }
+
+        let mut expiries = Vec::new(&env);
+        let mut weekly = now;
+        for _ in 0..schedule.weekly_expiries {
+            weekly = calendar::next_weekly_expiry(weekly);
+            expiries.push_back(weekly);
+        }
+        let (mut year, mut month) = calendar::month_of(now);
+        let mut listed_months = 0;
+        while listed_months < schedule.monthly_expiries {
+            let expiry = calendar::monthly_expiry(year, month);
+            if expiry > now {
+                expiries.push_back(expiry);
+                listed_months += 1;
+            }
+            (year, month) = calendar::next_month(year, month);
+        }
+
+        let mut created = Vec::new(&env);
+        for expiry in expiries.iter() {
+            for strike in strikes.iter() {
+                let strike = round_to_tick(&env, strike, schedule.tick_size);
+                if strike <= 0 {
+                    continue;
+                }
+                for opt_type in [OptionType::Call, OptionType::Put] {
+                    if let Some(series_id) = Self::create_series(
+                        &env,
+                        pool_id,
+                        opt_type,
+                        strike,
+                        expiry,
+                        schedule.style.clone(),
+                    ) {
+                        created.push_back(series_id);
+                    }
+                }
+            }
+        }
+
+        created
+    }
+
+    /// Delist all expired series of a pool - callable by anyone
+    pub fn archive_expired_series(env: Env, pool_id: u64) -> u32 {
+        Self::get_pool(env.clone(), pool_id);
+        Self::archive_series(&env, pool_id)
+    }
+
+    /// Exercise an option (American-style)
+    pub fn exercise_option(env: Env, option_id: u64) -> i128 {
+        let option = Self::get_option(env.clone(), option_id);
+
+        if !option.is_active {
+            panic_with_error!(&env, OptionsError::OptionNotActive);
+        }
+
+        option.buyer.require_auth();
+
+        Self::settle_exercise(&env, option_id, option)
+    }
+
+    /// Expire an option (release collateral)
+    pub fn expire_option(env: Env, option_id: u64) {
+        let option = Self::get_option(env.clone(), option_id);
+
+        if !option.is_active {
+            panic_with_error!(&env, OptionsError::OptionNotActive);
+        }
+
+        if env.ledger().timestamp() <= Self::exercise_deadline(&option) {
+            panic_with_error!(&env, OptionsError::OptionExpired);
+        }
+
+        Self::close_expired(&env, option_id, option);
+    }
+
+    /// Admin function to create a covered-call or cash-secured-put vault on a pool
+    pub fn create_vault(
+        env: Env,
+        pool_id: u64,
+        strategy: VaultStrategy,
+        strike_offset_bps: u32,
+        tick_size: i128,
+    ) -> u64 {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if !pool.is_active {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+        if strike_offset_bps >= BPS as u32 || tick_size <= 0 {
+            panic_with_error!(&env, OptionsError::InvalidSchedule);
+        }
+
+        let vault_id = Self::get_vault_counter(env.clone());
+        let vault = VaultData {
+            vault_id,
+            pool_id,
+            strategy: strategy.clone(),
+            strike_offset_bps,
+            tick_size,
+            epoch: 0,
+            series_id: None,
+            total_assets: 0,
+            total_proceeds: 0,
+            locked: 0,
+            total_shares: 0,
+            pending_deposits: 0,
+        };
+
+        env.storage()
+            .persistent()
+            .set(&DataKey::Vault(vault_id), &vault);
+        env.storage()
+            .instance()
+            .set(&DataKey::VaultCounter, &(vault_id + 1));
+
+        env.events()
+            .publish((VAULT_CREATED, admin), (vault_id, pool_id, strategy));
+
+        vault_id
+    }
+
+    /// Queue a vault deposit; it is converted to shares at the next epoch boundary
+    pub fn vault_deposit(env: Env, vault_id: u64, depositor: Address, amount: i128) {
+        depositor.require_auth();
+
+        if amount <= 0 {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+
+        let mut vault = Self::get_vault(env.clone(), vault_id);
+        let pool = Self::get_pool(env.clone(), vault.pool_id);
+        if !pool.is_active {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+
+        let (deposit_token, _) = Self::vault_tokens(&vault, &pool);
+        let token_client = TokenClient::new(&env, &deposit_token);
+        token_client.transfer(&depositor, &env.current_contract_address(), &amount);
+
+        let mut queue = Self::get_vault_deposit_queue(env.clone(), vault_id);
+        queue.push_back(QueuedRequest {
+            account: depositor.clone(),
+            amount,
+        });
+        env.storage()
+            .persistent()
+            .set(&DataKey::VaultDepositQueue(vault_id), &queue);
+
+        vault.pending_deposits = add(&env, vault.pending_deposits, amount);
+        env.storage()
+            .persistent()
+            .set(&DataKey::Vault(vault_id), &vault);
+
+        env.events()
+            .publish((VAULT_DEPOSIT_QUEUED, depositor), (vault_id, amount));
+    }
+
+    /// Queue vault shares for redemption at the next epoch boundary
+    pub fn vault_request_withdrawal(env: Env, vault_id: u64, owner: Address, shares: i128) {
+        owner.require_auth();
+
+        Self::get_vault(env.clone(), vault_id);
+
+        let owned = Self::get_vault_shares(env.clone(), vault_id, owner.clone());
+        if shares <= 0 || owned < shares {
+            panic_with_error!(&env, OptionsError::InsufficientShares);
+        }
+
+        // Queued shares leave the owner's balance but stay in the vault until the epoch closes
+        env.storage().persistent().set(
+            &DataKey::VaultShares(vault_id, owner.clone()),
+            &sub(&env, owned, shares),
+        );
+
+        let mut queue = Self::get_vault_withdrawal_queue(env.clone(), vault_id);
+        queue.push_back(QueuedRequest {
+            account: owner.clone(),
+            amount: shares,
+        });
+        env.storage()
+            .persistent()
+            .set(&DataKey::VaultWithdrawalQueue(vault_id), &queue);
+
+        env.events()
+            .publish((VAULT_WITHDRAWAL_QUEUED, owner), (vault_id, shares));
+    }
+
+    /// Close the finished epoch, process queued withdrawals and deposits, then list the
+    /// next OTM series for the vault to sell. Keeper-callable; returns the new series ID.
+    /// While the pool is paused or winding down nothing new is listed: queued withdrawals
+    /// are still paid, queued deposits are refunded, and None is returned.
+    pub fn roll_vault(env: Env, vault_id: u64) -> Option<u64> {
+        let mut vault = Self::get_vault(env.clone(), vault_id);
+        let pool = Self::get_pool(env.clone(), vault.pool_id);
+
+        let now = env.ledger().timestamp();
+        if let Some(series_id) = vault.series_id {
+            let series = Self::get_series(env.clone(), series_id);
+            if now <= series.expiry + EUROPEAN_EXERCISE_WINDOW {
+                panic_with_error!(&env, OptionsError::EpochInProgress);
+            }
+        }
+
+        // Anything left unexercised after the settlement window expires worthless
+        for option_id in Self::get_vault_epoch_options(&env, vault_id).iter() {
+            let option = Self::get_option(env.clone(), option_id);
+            if option.is_active {
+                Self::close_expired(&env, option_id, option);
+            }
+        }
+        vault = Self::get_vault(env.clone(), vault_id);
+
+        let (deposit_token, proceeds_token) = Self::vault_tokens(&vault, &pool);
+        let deposit_client = TokenClient::new(&env, &deposit_token);
+        let proceeds_client = TokenClient::new(&env, &proceeds_token);
+
+        // Redemptions pay out a pro-rata slice of both vault assets
+        for request in Self::get_vault_withdrawal_queue(env.clone(), vault_id).iter() {
+            let assets_out =
+                mul_div_floor(&env, request.amount, vault.total_assets, vault.total_shares);
+            let proceeds_out = mul_div_floor(
+                &env,
+                request.amount,
+                vault.total_proceeds,
+                vault.total_shares,
+            );
+            vault.total_assets = sub(&env, vault.total_assets, assets_out);
+            vault.total_proceeds = sub(&env, vault.total_proceeds, proceeds_out);
+            vault.total_shares = sub(&env, vault.total_shares, request.amount);
+
+            if assets_out > 0 {
+                deposit_client.transfer(
+                    &env.current_contract_address(),
+                    &request.account,
+                    &assets_out,
+                );
+            }
+            if proceeds_out > 0 {
+                proceeds_client.transfer(
+                    &env.current_contract_address(),
+                    &request.account,
+                    &proceeds_out,
+                );
+            }
+
+            env.events().publish(
+                (VAULT_WITHDRAWN, request.account),
+                (vault_id, request.amount, assets_out, proceeds_out),
+            );
+        }
+
+        let deposits = Self::get_vault_deposit_queue(env.clone(), vault_id);
+        vault.pending_deposits = 0;
+        env.storage()
+            .persistent()
+            .remove(&DataKey::VaultWithdrawalQueue(vault_id));
+        env.storage()
+            .persistent()
+            .remove(&DataKey::VaultDepositQueue(vault_id));
+        env.storage()
+            .persistent()
+            .remove(&DataKey::VaultEpochOptions(vault_id));
+
+        if !pool.is_active {
+            for request in deposits.iter() {
+                deposit_client.transfer(
+                    &env.current_contract_address(),
+                    &request.account,
+                    &request.amount,
+                );
+            }
+            vault.series_id = None;
+            env.storage()
+                .persistent()
+                .set(&DataKey::Vault(vault_id), &vault);
+            return None;
+        }
+
+        // Deposits mint shares against NAV, with proceeds valued at spot in deposit units
+        let spot = Self::get_price_from_feed(env.clone(), pool.price_feed.clone());
+        for request in deposits.iter() {
+            let proceeds_value = match vault.strategy {
+                VaultStrategy::CoveredCall => convert_ceil(
+                    &env,
+                    vault.total_proceeds,
+                    SCALE,
+                    spot,
+                    pool.stable_decimals,
+                    pool.underlying_decimals,
+                ),
+                VaultStrategy::CashSecuredPut => convert_ceil(
+                    &env,
+                    vault.total_proceeds,
+                    spot,
+                    SCALE,
+                    pool.underlying_decimals,
+                    pool.stable_decimals,
+                ),
+            };
+            let nav = add(&env, vault.total_assets, proceeds_value);
+            let shares = if vault.total_shares == 0 || nav == 0 {
+                request.amount
+            } else {
+                mul_div_floor(&env, request.amount, vault.total_shares, nav)
+            };
+
+            vault.total_assets = add(&env, vault.total_assets, request.amount);
+            vault.total_shares = add(&env, vault.total_shares, shares);
+            let owned = Self::get_vault_shares(env.clone(), vault_id, request.account.clone());
+            env.storage().persistent().set(
+                &DataKey::VaultShares(vault_id, request.account),
+                &add(&env, owned, shares),
+            );
+        }
+
+        // Next epoch sells the OTM strike expiring on the coming Friday
+        let (opt_type, raw_strike) = match vault.strategy {
+            VaultStrategy::CoveredCall => (
+                OptionType::Call,
+                mul_div_floor(&env, spot, BPS + vault.strike_offset_bps as i128, BPS),
+            ),
+            VaultStrategy::CashSecuredPut => (
+                OptionType::Put,
+                mul_div_floor(&env, spot, BPS - vault.strike_offset_bps as i128, BPS),
+            ),
+        };
+        let strike = round_to_tick(&env, raw_strike, vault.tick_size);
+        if strike <= 0 {
+            panic_with_error!(&env, OptionsError::InvalidPrice);
+        }
+        let expiry = calendar::next_weekly_expiry(now);
+
+        let series_id = Self::create_series(
+            &env,
+            vault.pool_id,
+            opt_type.clone(),
+            strike,
+            expiry,
+            OptionStyle::European,
+        )
+        .unwrap_or_else(|| {
+            env.storage()
+                .persistent()
+                .get(&DataKey::SeriesExists(
+                    vault.pool_id,
+                    opt_type,
+                    strike,
+                    expiry,
+                    OptionStyle::European,
+                ))
+                .unwrap()
+        });
+
+        vault.epoch += 1;
+        vault.series_id = Some(series_id);
+        env.storage()
+            .persistent()
+            .set(&DataKey::Vault(vault_id), &vault);
+
+        env.events().publish(
+            (VAULT_ROLLED, vault_id),
+            (vault.epoch, series_id, strike, expiry),
+        );
+
+        Some(series_id)
+    }
+
+    /// Buy an option written by a vault from its current epoch series
+    pub fn buy_vault_option(env: Env, vault_id: u64, buyer: Address, amount: i128) -> u64 {
+        buyer.require_auth();
+
+        let mut vault = Self::get_vault(env.clone(), vault_id);
+        let pool = Self::get_pool(env.clone(), vault.pool_id);
+        if !pool.is_active {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+
+        let series_id = vault
+            .series_id
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::SeriesNotFound));
+        let series = Self::get_series(env.clone(), series_id);
+        if series.expiry <= env.ledger().timestamp() {
+            panic_with_error!(&env, OptionsError::OptionExpired);
+        }
+        if amount <= 0 {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+        Self::require_min_trade(&env, amount);
+
+        let premium = Self::model_premium(
+            &env,
+            &pool,
+            &series.opt_type,
+            series.strike,
+            series.expiry,
+            amount,
+            true,
+        );
+        let collateral = match vault.strategy {
+            VaultStrategy::CoveredCall => Self::underlying_units_ceil(&env, &pool, amount),
+            VaultStrategy::CashSecuredPut => {
+                value_ceil(&env, series.strike, amount, pool.stable_decimals)
+            }
+        };
+        if vault.total_assets - vault.locked < collateral {
+            panic_with_error!(&env, OptionsError::InsufficientLiquidity);
+        }
+
+        let token_client = TokenClient::new(&env, &pool.stable_token);
+        token_client.transfer(&buyer, &env.current_contract_address(), &premium);
+
+        // Premium is paid in stable, which is the proceeds side of a covered-call vault
+        match vault.strategy {
+            VaultStrategy::CoveredCall => {
+                vault.total_proceeds = add(&env, vault.total_proceeds, premium)
+            }
+            VaultStrategy::CashSecuredPut => {
+                vault.total_assets = add(&env, vault.total_assets, premium)
+            }
+        }
+        vault.locked = add(&env, vault.locked, collateral);
+        env.storage()
+            .persistent()
+            .set(&DataKey::Vault(vault_id), &vault);
+
+        let option_id = Self::store_option(
+            &env,
+            OptionData {
+                pool_id: vault.pool_id,
+                buyer,
+                opt_type: series.opt_type,
+                strike: series.strike,
+                expiry: series.expiry,
+                amount,
+                premium_paid: premium,
+                collateral,
+                is_exercised: false,
+                is_active: true,
+                style: series.style,
+                series_id: Some(series_id),
+                settlement: SettlementType::Physical,
+                writer: OptionWriter::Vault(vault_id),
+                barrier: OptionBarrier::NoBarrier,
+                payoff: OptionPayoff::Vanilla,
+            },
+        );
+        Self::update_open_interest(&env, series_id, amount);
+
+        let mut sold = Self::get_vault_epoch_options(&env, vault_id);
+        sold.push_back(option_id);
+        env.storage()
+            .persistent()
+            .set(&DataKey::VaultEpochOptions(vault_id), &sold);
+
+        option_id
+    }
+
+    /// Buy a multi-leg strategy (spreads, straddles, strangles, ...) in one order.
+    /// The package is priced as the sum of its legs and collateralized by the max
+    /// loss of the combined payoff, which either side can be asked to post.
+    pub fn buy_strategy(env: Env, pool_id: u64, buyer: Address, legs: Vec<StrategyLeg>) -> u64 {
+        buyer.require_auth();
+
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if !pool.is_active {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+        if legs.is_empty() || legs.len() > MAX_STRATEGY_LEGS {
+            panic_with_error!(&env, OptionsError::InvalidStrategy);
+        }
+
+        let mut expiry = 0u64;
+        let mut leg_premiums = Vec::new(&env);
+        let mut net_premium = 0i128;
+        for leg in legs.iter() {
+            Self::require_expiry_in_bounds(&env, pool_id, leg.expiry);
+            if leg.amount <= 0 || leg.strike <= 0 {
+                panic_with_error!(&env, OptionsError::InvalidAmount);
+            }
+            Self::require_min_trade(&env, leg.amount);
+
+            let leg_premium = |round_up: bool| {
+                Self::model_premium(
+                    &env,
+                    &pool,
+                    &leg.opt_type,
+                    leg.strike,
+                    leg.expiry,
+                    leg.amount,
+                    round_up,
+                )
+            };
+            let premium = match leg.side {
+                PositionSide::Long => leg_premium(true),
+                PositionSide::Short => -leg_premium(false),
+            };
+            leg_premiums.push_back(premium);
+            net_premium = add(&env, net_premium, premium);
+            expiry = expiry.max(leg.expiry);
+        }
+
+        let (pool_collateral, owner_collateral) = Self::strategy_max_losses(&env, &pool, &legs);
+
+        // A net credit is paid out of pool liquidity, so it must be free as well
+        if Self::option_capacity(&env, pool_id) < add(&env, pool_collateral, (-net_premium).max(0))
+        {
+            panic_with_error!(&env, OptionsError::InsufficientLiquidity);
+        }
+
+        let token_client = TokenClient::new(&env, &pool.stable_token);
+        let owner_payment = add(&env, owner_collateral, net_premium);
+        if owner_payment > 0 {
+            token_client.transfer(&buyer, &env.current_contract_address(), &owner_payment);
+        } else if owner_payment < 0 {
+            token_client.transfer(&env.current_contract_address(), &buyer, &-owner_payment);
+        }
+
+        let liquidity = Self::get_pool_total_liquidity(env.clone(), pool_id);
+        env.storage().persistent().set(
+            &DataKey::PoolTotalLiquidity(pool_id),
+            &add(&env, liquidity, net_premium),
+        );
+        let locked_collateral = Self::get_pool_locked_collateral(env.clone(), pool_id);
+        env.storage().persistent().set(
+            &DataKey::PoolLockedCollateral(pool_id),
+            &add(&env, locked_collateral, pool_collateral),
+        );
+
+        let strategy_id = Self::get_strategy_counter(env.clone());
+        let mut open_strategies = Self::get_pool_open_strategies(env.clone(), pool_id);
+        Self::require_open_slot(
+            &env,
+            open_strategies.len(),
+            MAX_POOL_OPEN_STRATEGIES,
+            pool_collateral,
+            Self::option_capacity(&env, pool_id),
+        );
+        open_strategies.push_back(strategy_id);
+        env.storage()
+            .persistent()
+            .set(&DataKey::PoolOpenStrategies(pool_id), &open_strategies);
+        let strategy = StrategyData {
+            strategy_id,
+            pool_id,
+            owner: buyer.clone(),
+            legs: legs.clone(),
+            net_premium,
+            pool_collateral,
+            owner_collateral,
+            settled_payoff: 0,
+            expiry,
+            is_active: true,
+        };
+        env.storage()
+            .persistent()
+            .set(&DataKey::Strategy(strategy_id), &strategy);
+        for (index, leg) in legs.iter().enumerate() {
+            env.storage().persistent().set(
+                &DataKey::StrategyLeg(strategy_id, index as u32),
+                &StrategyLegRecord {
+                    strategy_id,
+                    leg,
+                    premium: leg_premiums.get_unchecked(index as u32),
+                    settlement_price: 0,
+                    payoff: 0,
+                    is_settled: false,
+                },
+            );
+        }
+        env.storage()
+            .instance()
+            .set(&DataKey::StrategyCounter, &(strategy_id + 1));
+
+        env.events().publish(
+            (STRATEGY_PURCHASED, buyer),
+            (
+                strategy_id,
+                pool_id,
+                net_premium,
+                pool_collateral,
+                owner_collateral,
+            ),
+        );
+
+        strategy_id
+    }
+
+    /// Settle every expired leg of a strategy at the oracle price at that leg's expiry.
+    /// Anyone can call this; a long leg left unsettled past the European exercise window
+    /// is forfeited, while short legs still settle at their expiry price. The package pays
+    /// out once its last leg has settled. Returns the amount paid to the owner, 0 until then.
+    pub fn settle_strategy(env: Env, strategy_id: u64) -> i128 {
+        let strategy = Self::get_strategy(env.clone(), strategy_id);
+        if !strategy.is_active {
+            panic_with_error!(&env, OptionsError::OptionNotActive);
+        }
+
+        let pool = Self::get_pool(env.clone(), strategy.pool_id);
+        let (strategy, settled, open) = Self::settle_expired_legs(&env, &pool, strategy);
+        if open > 0 {
+            if settled == 0 {
+                panic_with_error!(&env, OptionsError::NotExercisable);
+            }
+            env.storage()
+                .persistent()
+                .set(&DataKey::Strategy(strategy_id), &strategy);
+            return 0;
+        }
+        Self::pay_out_strategy(&env, &pool, strategy)
+    }
+
+    /// Close a strategy early. Expired legs settle at their expiry price as in
+    /// `settle_strategy` and the rest at the current oracle price. Only the owner can
+    /// close. Returns the amount paid to the owner.
+    pub fn close_strategy(env: Env, strategy_id: u64) -> i128 {
+        let strategy = Self::get_strategy(env.clone(), strategy_id);
+        if !strategy.is_active {
+            panic_with_error!(&env, OptionsError::OptionNotActive);
+        }
+        strategy.owner.require_auth();
+
+        let pool = Self::get_pool(env.clone(), strategy.pool_id);
+        let (mut strategy, _, open) = Self::settle_expired_legs(&env, &pool, strategy);
+        if open > 0 {
+            let price = Self::get_price_from_feed(env.clone(), pool.price_feed.clone());
+            for index in 0..strategy.legs.len() {
+                let key = DataKey::StrategyLeg(strategy_id, index);
+                let record: StrategyLegRecord = env.storage().persistent().get(&key).unwrap();
+                if !record.is_settled {
+                    let payoff = Self::settle_strategy_leg(
+                        &env,
+                        &pool,
+                        &strategy.owner,
+                        index,
+                        record,
+                        price,
+                    );
+                    strategy.settled_payoff = add(&env, strategy.settled_payoff, payoff);
+                }
+            }
+        }
+        Self::pay_out_strategy(&env, &pool, strategy)
+    }
+
+    /// Write a cash-settled option against the writer's own collateral and list an ask.
+    /// The collateral is escrowed until the order is cancelled or the option settles.
+    pub fn write_option(
+        env: Env,
+        pool_id: u64,
+        writer: Address,
+        opt_type: OptionType,
+        strike: i128,
+        expiry: u64,
+        amount: i128,
+        ask_premium: i128,
+    ) -> u64 {
+        writer.require_auth();
+
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if !pool.is_active {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+        if expiry <= env.ledger().timestamp() {
+            panic_with_error!(&env, OptionsError::OptionExpired);
+        }
+        if amount <= 0 || strike <= 0 || ask_premium < 0 {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+        Self::require_min_trade(&env, amount);
+
+        // Same strike-sized collateral a pool-written option locks
+        let collateral = value_ceil(&env, strike, amount, pool.stable_decimals);
+        let token_client = TokenClient::new(&env, &pool.stable_token);
+        token_client.transfer(&writer, &env.current_contract_address(), &collateral);
+
+        let order_id = Self::get_order_counter(env.clone());
+        let order = OptionOrder {
+            order_id,
+            pool_id,
+            writer: writer.clone(),
+            opt_type,
+            strike,
+            expiry,
+            amount,
+            collateral,
+            ask_premium,
+            is_open: true,
+        };
+        env.storage()
+            .persistent()
+            .set(&DataKey::OptionOrder(order_id), &order);
+        env.storage()
+            .instance()
+            .set(&DataKey::OrderCounter, &(order_id + 1));
+
+        env.events().publish(
+            (ORDER_PLACED, writer),
+            (order_id, pool_id, strike, expiry, amount, ask_premium),
+        );
+
+        order_id
+    }
+
+    /// Fill a writer's order; the premium goes straight to the writer
+    pub fn fill_option_order(env: Env, order_id: u64, buyer: Address) -> u64 {
+        buyer.require_auth();
+
+        let mut order = Self::get_option_order(env.clone(), order_id);
+        if !order.is_open {
+            panic_with_error!(&env, OptionsError::OrderNotOpen);
+        }
+        if order.expiry <= env.ledger().timestamp() {
+            panic_with_error!(&env, OptionsError::OptionExpired);
+        }
+        let pool = Self::get_pool(env.clone(), order.pool_id);
+        if !pool.is_active {
+            panic_with_error!(&env, OptionsError::PoolNotActive);
+        }
+
+        order.is_open = false;
+        env.storage()
+            .persistent()
+            .set(&DataKey::OptionOrder(order_id), &order);
+
+        if order.ask_premium > 0 {
+            let token_client = TokenClient::new(&env, &pool.stable_token);
+            token_client.transfer(&buyer, &order.writer, &order.ask_premium);
+        }
+
+        let option_id = Self::store_option(
+            &env,
+            OptionData {
+                pool_id: order.pool_id,
+                buyer: buyer.clone(),
+                opt_type: order.opt_type,
+                strike: order.strike,
+                expiry: order.expiry,
+                amount: order.amount,
+                premium_paid: order.ask_premium,
+                collateral: order.collateral,
+                is_exercised: false,
+                is_active: true,
+                style: OptionStyle::American,
+                series_id: None,
+                settlement: SettlementType::Cash,
+                writer: OptionWriter::Account(order.writer),
+                barrier: OptionBarrier::NoBarrier,
+                payoff: OptionPayoff::Vanilla,
+            },
+        );
+
+        env.events()
+            .publish((ORDER_FILLED, buyer), (order_id, option_id));
+
+        option_id
+    }
+
+    /// Cancel an unfilled order and return the writer's collateral
+    pub fn cancel_option_order(env: Env, order_id: u64) {
+        let mut order = Self::get_option_order(env.clone(), order_id);
+        order.writer.require_auth();
+
+        if !order.is_open {
+            panic_with_error!(&env, OptionsError::OrderNotOpen);
+        }
+
+        order.is_open = false;
+        env.storage()
+            .persistent()
+            .set(&DataKey::OptionOrder(order_id), &order);
+
+        let pool = Self::get_pool(env.clone(), order.pool_id);
+        let token_client = TokenClient::new(&env, &pool.stable_token);
+        token_client.transfer(
+            &env.current_contract_address(),
+            &order.writer,
+            &order.collateral,
+        );
+
+        env.events()
+            .publish((ORDER_CANCELLED, order.writer), order_id);
+    }
+
+    // View functions for pools
+    pub fn get_pool_counter(env: Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&DataKey::PoolCounter)
+            .unwrap_or(0)
+    }
+
+    pub fn get_pool(env: Env, pool_id: u64) -> PoolData {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Pool(pool_id))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::PoolNotFound))
+    }
+
+    /// IDs of every pool for the pair that has not been retired, in creation order
+    pub fn get_pool_by_assets(
+        env: Env,
+        stable_token: Address,
+        underlying_asset: Address,
+    ) -> Vec<u64> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PairPools(stable_token, underlying_asset))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Quotes for a cash-settled option from every active pool for the pair whose expiry
+    /// bounds allow it, including pools without the capacity to write it
+    pub fn get_pair_quotes(
+        env: Env,
+        stable_token: Address,
+        underlying_asset: Address,
+        opt_type: OptionType,
+        strike: i128,
+        expiry: u64,
+        amount: i128,
+    ) -> Vec<PoolQuote> {
+        if amount <= 0 || strike <= 0 {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+        Self::require_min_trade(&env, amount);
+        if expiry <= env.ledger().timestamp() {
+            panic_with_error!(&env, OptionsError::OptionExpired);
+        }
+
+        let mut quotes = Vec::new(&env);
+        for pool_id in Self::get_pool_by_assets(env.clone(), stable_token, underlying_asset).iter()
+        {
+            if let Some(quote) = Self::pool_quote(&env, pool_id, &opt_type, strike, expiry, amount)
+            {
+                quotes.push_back(quote);
+            }
+        }
+        quotes
+    }
+
+    /// Route a buyer to the pool for the pair with the lowest premium plus fee that can
+    /// lock the option's collateral; ties go to the older pool
+    pub fn get_best_pool(
+        env: Env,
+        stable_token: Address,
+        underlying_asset: Address,
+        opt_type: OptionType,
+        strike: i128,
+        expiry: u64,
+        amount: i128,
+    ) -> PoolQuote {
+        let quotes = Self::get_pair_quotes(
+            env.clone(),
+            stable_token,
+            underlying_asset,
+            opt_type,
+            strike,
+            expiry,
+            amount,
+        );
+        Self::cheapest_full_fill(&quotes)
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::InsufficientLiquidity))
+    }
+
+    pub fn get_pool_total_liquidity(env: Env, pool_id: u64) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolTotalLiquidity(pool_id))
+            .unwrap_or(0)
+    }
+
+    pub fn get_pool_locked_collateral(env: Env, pool_id: u64) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolLockedCollateral(pool_id))
+            .unwrap_or(0)
+    }
+
+    pub fn get_pool_total_lp_shares(env: Env, pool_id: u64) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolTotalLpShares(pool_id))
+            .unwrap_or(0)
+    }
+
+    pub fn get_pool_lp_shares(env: Env, pool_id: u64, provider: Address) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolLpShares(pool_id, provider))
+            .unwrap_or(0)
+    }
+
+    pub fn get_withdrawal_queue(env: Env, pool_id: u64) -> Vec<QueuedRequest> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolWithdrawalQueue(pool_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    pub fn get_pool_queued_shares(env: Env, pool_id: u64) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolQueuedShares(pool_id))
+            .unwrap_or(0)
+    }
+
+    // Unlocked liquidity held back for queued withdrawals
+    pub fn get_pool_reserved_liquidity(env: Env, pool_id: u64) -> i128 {
+        Self::queued_withdrawal_value(&env, pool_id).min(Self::unlocked_liquidity(&env, pool_id))
+    }
+
+    pub fn get_withdrawal_estimate(
+        env: Env,
+        pool_id: u64,
+        provider: Address,
+    ) -> WithdrawalEstimate {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        let nav = Self::stable_nav(&Self::mark_to_market(&env, &pool)).max(0);
+        let total_lp_shares = Self::get_pool_total_lp_shares(env.clone(), pool_id);
+        let available = Self::unlocked_liquidity(&env, pool_id);
+
+        let mut position = None;
+        let mut queued_shares = 0i128;
+        let mut shares_ahead = 0i128;
+        for (index, request) in Self::get_withdrawal_queue(env.clone(), pool_id)
+            .iter()
+            .enumerate()
+        {
+            if request.account == provider {
+                position.get_or_insert(index as u32);
+                queued_shares = add(&env, queued_shares, request.amount);
+            } else if position.is_none() {
+                shares_ahead = add(&env, shares_ahead, request.amount);
+            }
+        }
+        let position =
+            position.unwrap_or_else(|| panic_with_error!(&env, OptionsError::WithdrawalNotQueued));
+
+        let value = |shares: i128| {
+            if total_lp_shares == 0 {
+                0
+            } else {
+                mul_div_floor(&env, shares, nav, total_lp_shares)
+            }
+        };
+        let queued_value = value(queued_shares);
+        let value_ahead = value(shares_ahead);
+
+        WithdrawalEstimate {
+            position,
+            queued_shares,
+            queued_value,
+            value_ahead,
+            fillable_now: (available - value_ahead).clamp(0, queued_value),
+        }
+    }
+
+    pub fn get_pool_underlying_liquidity(env: Env, pool_id: u64) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolUnderlyingLiquidity(pool_id))
+            .unwrap_or(0)
+    }
+
+    pub fn get_pool_locked_underlying(env: Env, pool_id: u64) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolLockedUnderlying(pool_id))
+            .unwrap_or(0)
+    }
+
+    pub fn get_pool_underlying_proceeds(env: Env, pool_id: u64) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolUnderlyingProceeds(pool_id))
+            .unwrap_or(0)
+    }
+
+    pub fn get_pool_delivered_underlying(env: Env, pool_id: u64) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolDeliveredUnderlying(pool_id))
+            .unwrap_or(0)
+    }
+
+    pub fn get_pool_underlying_total_shares(env: Env, pool_id: u64) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolUnderlyingTotalShares(pool_id))
+            .unwrap_or(0)
+    }
+
+    pub fn get_pool_underlying_lp_shares(env: Env, pool_id: u64, provider: Address) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolUnderlyingLpShares(pool_id, provider))
+            .unwrap_or(0)
+    }
+
+    // Other view functions
+    pub fn get_option_counter(env: Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&DataKey::OptionCounter)
+            .unwrap_or(0)
+    }
+
+    pub fn get_option(env: Env, option_id: u64) -> OptionData {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Option(option_id))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::OptionNotFound))
+    }
+
+    // Series view functions
+    pub fn get_pool_open_options(env: Env, pool_id: u64) -> Vec<u64> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolOpenOptions(pool_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    pub fn get_pool_open_strategies(env: Env, pool_id: u64) -> Vec<u64> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolOpenStrategies(pool_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Value the pool's open options and strategy legs at the model price, floored at
+    /// exercise value, and report its NAV net of that liability. LP shares are minted and
+    /// burned at this NAV.
+    pub fn get_pool_mark_to_market(env: Env, pool_id: u64) -> PoolMarkToMarket {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        Self::mark_to_market(&env, &pool)
+    }
+
+    /// `get_pool_mark_to_market` over `limit` open positions from index `start` only,
+    /// counting open options first and then open strategies; the pool's option liability
+    /// is the sum of `option_liability` over all pages.
+    pub fn get_pool_mark_to_market_page(
+        env: Env,
+        pool_id: u64,
+        start: u32,
+        limit: u32,
+    ) -> PoolMarkToMarket {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        let open_options = Self::get_pool_open_options(env.clone(), pool_id);
+        let open_strategies = Self::get_pool_open_strategies(env.clone(), pool_id);
+        let end = start.saturating_add(limit);
+        let page = |list: Vec<u64>, offset: u32| {
+            let from = start.saturating_sub(offset).min(list.len());
+            let to = end.saturating_sub(offset).min(list.len());
+            list.slice(from..to.max(from))
+        };
+        let options_len = open_options.len();
+        Self::mark_positions(
+            &env,
+            &pool,
+            page(open_options, 0),
+            page(open_strategies, options_len),
+        )
+    }
+
+    /// Model greeks of an active option at the current oracle spot, from the holder's side
+    pub fn get_option_greeks(env: Env, option_id: u64) -> OptionGreeks {
+        let option = Self::get_option(env.clone(), option_id);
+        if !option.is_active {
+            panic_with_error!(&env, OptionsError::OptionNotActive);
+        }
+        let pool = Self::get_pool(env.clone(), option.pool_id);
+        let spot = Self::get_price_from_feed(env.clone(), pool.price_feed.clone());
+        Self::option_greeks(&env, &pool, &option, spot)
+    }
+
+    /// Net greeks of the options and strategy legs a pool has written, plus what it would
+    /// pay out if all of them were exercised after a `shock_bps` move of spot either way
+    /// (at most 90%)
+    pub fn get_pool_risk(env: Env, pool_id: u64, shock_bps: u32) -> PoolRisk {
+        if shock_bps == 0 || shock_bps > MAX_RISK_SHOCK_BPS {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+        let pool = Self::get_pool(env.clone(), pool_id);
+        let spot = Self::get_price_from_feed(env.clone(), pool.price_feed.clone());
+        let shock = mul_div_floor(&env, spot, shock_bps as i128, BPS);
+
+        let open_options = Self::get_pool_open_options(env.clone(), pool_id);
+        let open_strategies = Self::get_pool_open_strategies(env.clone(), pool_id);
+        let mut risk = PoolRisk {
+            spot,
+            open_options: open_options.len(),
+            open_strategies: open_strategies.len(),
+            net_delta: 0,
+            net_gamma: 0,
+            total_vega: 0,
+            net_theta: 0,
+            shock_bps,
+            payout_up: 0,
+            payout_down: 0,
+        };
+        for option_id in open_options.iter() {
+            let option = Self::get_option(env.clone(), option_id);
+            let greeks = Self::option_greeks(&env, &pool, &option, spot);
+            risk.net_delta = sub(&env, risk.net_delta, greeks.delta);
+            risk.net_gamma = sub(&env, risk.net_gamma, greeks.gamma);
+            risk.total_vega = sub(&env, risk.total_vega, greeks.vega);
+            risk.net_theta = sub(&env, risk.net_theta, greeks.theta);
+            risk.payout_up = add(
+                &env,
+                risk.payout_up,
+                Self::exercise_value(&env, &pool, &option, add(&env, spot, shock)),
+            );
+            risk.payout_down = add(
+                &env,
+                risk.payout_down,
+                Self::exercise_value(&env, &pool, &option, sub(&env, spot, shock)),
+            );
+        }
+
+        // The pool is short the legs the owner is long and long the ones they are short
+        for strategy_id in open_strategies.iter() {
+            let strategy = Self::get_strategy(env.clone(), strategy_id);
+            let open_legs = Self::open_strategy_legs(&env, &strategy);
+            for leg in open_legs.iter() {
+                let option = Self::leg_option(&strategy, &leg);
+                let greeks = Self::option_greeks(&env, &pool, &option, spot);
+                let (delta, gamma, vega, theta) = match leg.side {
+                    PositionSide::Long => {
+                        (-greeks.delta, -greeks.gamma, -greeks.vega, -greeks.theta)
+                    }
+                    PositionSide::Short => (greeks.delta, greeks.gamma, greeks.vega, greeks.theta),
+                };
+                risk.net_delta = add(&env, risk.net_delta, delta);
+                risk.net_gamma = add(&env, risk.net_gamma, gamma);
+                risk.total_vega = add(&env, risk.total_vega, vega);
+                risk.net_theta = add(&env, risk.net_theta, theta);
+            }
+            risk.payout_up = add(
+                &env,
+                risk.payout_up,
+                Self::strategy_payout(&env, &pool, &strategy, &open_legs, add(&env, spot, shock)),
+            );
+            risk.payout_down = add(
+                &env,
+                risk.payout_down,
+                Self::strategy_payout(&env, &pool, &strategy, &open_legs, sub(&env, spot, shock)),
+            );
+        }
+        risk
+    }
+
+    pub fn get_series_counter(env: Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&DataKey::SeriesCounter)
+            .unwrap_or(0)
+    }
+
+    pub fn get_series(env: Env, series_id: u64) -> SeriesData {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Series(series_id))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::SeriesNotFound))
+    }
+
+    pub fn get_series_open_interest(env: Env, series_id: u64) -> i128 {
+        Self::get_series(env, series_id).open_interest
+    }
+
+    // Listed (tradeable) series of a pool
+    pub fn get_pool_series(env: Env, pool_id: u64) -> Vec<u64> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolSeries(pool_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    pub fn get_pool_archived_series(env: Env, pool_id: u64) -> Vec<u64> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolArchivedSeries(pool_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    // Vault view functions
+    pub fn get_vault_counter(env: Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&DataKey::VaultCounter)
+            .unwrap_or(0)
+    }
+
+    pub fn get_vault(env: Env, vault_id: u64) -> VaultData {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Vault(vault_id))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::VaultNotFound))
+    }
+
+    pub fn get_vault_shares(env: Env, vault_id: u64, owner: Address) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::VaultShares(vault_id, owner))
+            .unwrap_or(0)
+    }
+
+    pub fn get_vault_deposit_queue(env: Env, vault_id: u64) -> Vec<QueuedRequest> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::VaultDepositQueue(vault_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    pub fn get_vault_withdrawal_queue(env: Env, vault_id: u64) -> Vec<QueuedRequest> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::VaultWithdrawalQueue(vault_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    pub fn get_strategy_counter(env: Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&DataKey::StrategyCounter)
+            .unwrap_or(0)
+    }
+
+    pub fn get_strategy(env: Env, strategy_id: u64) -> StrategyData {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Strategy(strategy_id))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::InvalidStrategy))
+    }
+
+    pub fn get_strategy_leg(env: Env, strategy_id: u64, leg_index: u32) -> StrategyLegRecord {
+        env.storage()
+            .persistent()
+            .get(&DataKey::StrategyLeg(strategy_id, leg_index))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::InvalidStrategy))
+    }
+
+    pub fn get_order_counter(env: Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&DataKey::OrderCounter)
+            .unwrap_or(0)
+    }
+
+    pub fn get_option_order(env: Env, order_id: u64) -> OptionOrder {
+        env.storage()
+            .persistent()
+            .get(&DataKey::OptionOrder(order_id))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::OrderNotFound))
+    }
+
+    pub fn get_pool_quoters(env: Env, pool_id: u64) -> Vec<BytesN<32>> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolQuoters(pool_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    pub fn is_quote_nonce_used(env: Env, quoter: BytesN<32>, nonce: u64) -> bool {
+        env.storage()
+            .persistent()
+            .has(&DataKey::QuoteNonce(quoter, nonce))
+    }
+
+    /// Get the funding account of a perpetual option
+    pub fn get_perpetual_funding(env: Env, option_id: u64) -> PerpetualFunding {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PerpetualFunding(option_id))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::NotPerpetual))
+    }
+
+    pub fn get_series_schedule(env: Env, pool_id: u64) -> SeriesSchedule {
+        env.storage()
+            .persistent()
+            .get(&DataKey::SeriesSchedule(pool_id))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::SeriesScheduleNotSet))
+    }
+
+    pub fn get_admin(env: Env) -> Address {
+        env.storage()
+            .instance()
+            .get(&DataKey::Admin)
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::NotInitialized))
+    }
+
+    // Get price from SEP-40 oracle
+    pub fn get_price_from_feed(env: Env, price_feed: Address) -> i128 {
+        // Create a client for the SEP-40 price feed oracle
+        let price_feed_client = PriceFeedClient::new(&env, &price_feed);
+
+        // Create an Asset instance for the asset we want to get the price for
+        // In this case, we're using the default asset (typically the base asset of the oracle)
+        let asset = Asset::Other(Symbol::new(&env, "XLM"));
+
+        // Get the latest price from the oracle
+        let price_data_opt = price_feed_client.lastprice(&asset);
+
+        // Handle the Option<PriceData> return type
+        match price_data_opt {
+            Some(price_data) => {
+                // Extract the price from the PriceData
+                price_data.price
+            }
+            None => {
+                // If no price is available, panic with an error
+                panic_with_error!(&env, OptionsError::InvalidPrice);
+            }
+        }
+    }
+
+    pub fn get_pool_volatility(env: Env, pool_id: u64) -> i128 {
+        Self::get_pool_params(env, pool_id).volatility
+    }
+
+    pub fn get_pool_params(env: Env, pool_id: u64) -> PoolParams {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolParams(pool_id))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::PoolNotFound))
+    }
+
+    /// Admin function to announce a change of pool parameters. It can be executed once
+    /// PARAMS_TIMELOCK has passed, giving LPs time to exit first.
+    pub fn queue_pool_params(env: Env, pool_id: u64, params: PoolParams) -> u64 {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        Self::get_pool(env.clone(), pool_id);
+        if env
+            .storage()
+            .persistent()
+            .has(&DataKey::PendingPoolParams(pool_id))
+        {
+            panic_with_error!(&env, OptionsError::ParamsChangePending);
+        }
+
+        Self::validate_pool_params(&env, &params);
+
+        let queued_at = env.ledger().timestamp();
+        let eta = queued_at + PARAMS_TIMELOCK;
+        env.storage().persistent().set(
+            &DataKey::PendingPoolParams(pool_id),
+            &PendingPoolParams {
+                params: params.clone(),
+                queued_at,
+                eta,
+            },
+        );
+
+        env.events()
+            .publish((PARAMS_QUEUED, pool_id), (params, eta));
+        eta
+    }
+
+    /// Apply a queued parameter change once its timelock has passed. Callable by anyone.
+    pub fn execute_pool_params(env: Env, pool_id: u64) -> PoolParams {
+        let pending = Self::get_pending_pool_params(env.clone(), pool_id);
+        if env.ledger().timestamp() < pending.eta {
+            panic_with_error!(&env, OptionsError::TimelockNotElapsed);
+        }
+
+        env.storage()
+            .persistent()
+            .remove(&DataKey::PendingPoolParams(pool_id));
+        Self::apply_pool_params(&env, pool_id, &pending.params);
+        pending.params
+    }
+
+    /// Admin function to drop a queued parameter change
+    pub fn cancel_pool_params(env: Env, pool_id: u64) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        let pending = Self::get_pending_pool_params(env.clone(), pool_id);
+        env.storage()
+            .persistent()
+            .remove(&DataKey::PendingPoolParams(pool_id));
+
+        env.events()
+            .publish((PARAMS_CANCELLED, pool_id), pending.params);
+    }
+
+    pub fn get_pending_pool_params(env: Env, pool_id: u64) -> PendingPoolParams {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PendingPoolParams(pool_id))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::NoPendingParams))
+    }
+
+    /// Propose a pool parameter change for the pool's LPs to vote on. Voting weight is
+    /// the LP shares each voter held before the proposal's ledger.
+    pub fn propose_pool_params(
+        env: Env,
+        pool_id: u64,
+        proposer: Address,
+        params: PoolParams,
+    ) -> u64 {
+        proposer.require_auth();
+
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if Self::get_pool_lp_shares(env.clone(), pool_id, proposer.clone()) <= 0 {
+            panic_with_error!(&env, OptionsError::InsufficientShares);
+        }
+        Self::validate_pool_params(&env, &params);
+
+        // The first deposit's dead shares and shares escrowed in the withdrawal queue can
+        // never vote, so they don't count toward quorum
+        let total_shares = sub(
+            &env,
+            Self::get_pool_total_lp_shares(env.clone(), pool_id),
+            add(
+                &env,
+                Self::token_units(&env, DEAD_SHARES, pool.stable_decimals),
+                Self::get_pool_queued_shares(env.clone(), pool_id),
+            ),
+        );
+
+        let proposal_id = Self::get_proposal_counter(env.clone());
+        let voting_ends = env.ledger().timestamp() + VOTING_PERIOD;
+        let eta = voting_ends + PARAMS_TIMELOCK;
+        let proposal = Proposal {
+            proposal_id,
+            pool_id,
+            proposer: proposer.clone(),
+            params,
+            snapshot_ledger: env.ledger().sequence(),
+            total_shares,
+            votes_for: 0,
+            votes_against: 0,
+            voting_ends,
+            eta,
+            expires_at: eta + PROPOSAL_EXECUTION_WINDOW,
+            executed: false,
+        };
+        env.storage()
+            .persistent()
+            .set(&DataKey::Proposal(proposal_id), &proposal);
+        env.storage()
+            .instance()
+            .set(&DataKey::ProposalCounter, &(proposal_id + 1));
+
+        env.events().publish(
+            (PROPOSAL_CREATED, proposer),
+            (proposal_id, pool_id, voting_ends),
+        );
+
+        proposal_id
+    }
+
+    /// Vote on an open proposal with the shares held at its snapshot
+    pub fn cast_vote(env: Env, proposal_id: u64, voter: Address, support: bool) -> i128 {
+        voter.require_auth();
+
+        let mut proposal = Self::get_proposal(env.clone(), proposal_id);
+        if env.ledger().timestamp() >= proposal.voting_ends {
+            panic_with_error!(&env, OptionsError::VotingClosed);
+        }
+        let vote_key = DataKey::ProposalVote(proposal_id, voter.clone());
+        if env.storage().persistent().has(&vote_key) {
+            panic_with_error!(&env, OptionsError::AlreadyVoted);
+        }
+
+        let weight = Self::get_lp_shares_at(
+            env.clone(),
+            proposal.pool_id,
+            voter.clone(),
+            proposal.snapshot_ledger,
+        );
+        if weight <= 0 {
+            panic_with_error!(&env, OptionsError::InsufficientShares);
+        }
+
+        if support {
+            proposal.votes_for = add(&env, proposal.votes_for, weight);
+        } else {
+            proposal.votes_against = add(&env, proposal.votes_against, weight);
+        }
+        env.storage().persistent().set(&vote_key, &weight);
+        env.storage()
+            .persistent()
+            .set(&DataKey::Proposal(proposal_id), &proposal);
+
+        env.events()
+            .publish((VOTE_CAST, voter), (proposal_id, support, weight));
+
+        weight
+    }
+
+    /// Apply a proposal that reached quorum with a majority once voting and the timelock
+    /// after it have passed, and before it expires. Callable by anyone.
+    pub fn execute_proposal(env: Env, proposal_id: u64) -> PoolParams {
+        let mut proposal = Self::get_proposal(env.clone(), proposal_id);
+        if proposal.executed || env.ledger().timestamp() > proposal.expires_at {
+            panic_with_error!(&env, OptionsError::ProposalNotPassed);
+        }
+        if env.ledger().timestamp() < proposal.eta {
+            panic_with_error!(&env, OptionsError::TimelockNotElapsed);
+        }
+        let quorum = mul_div_ceil(&env, proposal.total_shares, PROPOSAL_QUORUM_BPS, BPS);
+        if proposal.votes_for <= proposal.votes_against || proposal.votes_for < quorum {
+            panic_with_error!(&env, OptionsError::ProposalNotPassed);
+        }
+
+        proposal.executed = true;
+        env.storage()
+            .persistent()
+            .set(&DataKey::Proposal(proposal_id), &proposal);
+        Self::apply_pool_params(&env, proposal.pool_id, &proposal.params);
+
+        env.events().publish(
+            (PROPOSAL_EXECUTED, proposal.pool_id),
+            (proposal_id, proposal.votes_for, proposal.votes_against),
+        );
+
+        proposal.params
+    }
+
+    pub fn get_proposal_counter(env: Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&DataKey::ProposalCounter)
+            .unwrap_or(0)
+    }
+
+    pub fn get_proposal(env: Env, proposal_id: u64) -> Proposal {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Proposal(proposal_id))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::ProposalNotFound))
+    }
+
+    /// Weight a voter cast on a proposal, 0 if they haven't voted
+    pub fn get_vote(env: Env, proposal_id: u64, voter: Address) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::ProposalVote(proposal_id, voter))
+            .unwrap_or(0)
+    }
+
+    /// LP shares a provider held at the end of the ledger before `ledger`
+    pub fn get_lp_shares_at(env: Env, pool_id: u64, provider: Address, ledger: u32) -> i128 {
+        let checkpoints: Vec<ShareCheckpoint> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::LpShareCheckpoints(pool_id, provider))
+            .unwrap_or(Vec::new(&env));
+        let mut shares = 0;
+        for checkpoint in checkpoints.iter() {
+            if checkpoint.ledger >= ledger {
+                break;
+            }
+            shares = checkpoint.shares;
+        }
+        shares
+    }
+
+    pub fn get_protocol_fees(env: Env, stable_token: Address) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::ProtocolFees(stable_token))
+            .unwrap_or(0)
+    }
+
+    /// Admin function to withdraw collected protocol fees
+    pub fn withdraw_protocol_fees(env: Env, stable_token: Address, to: Address, amount: i128) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        let fees = Self::get_protocol_fees(env.clone(), stable_token.clone());
+        if amount <= 0 || amount > fees {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+        env.storage().persistent().set(
+            &DataKey::ProtocolFees(stable_token.clone()),
+            &sub(&env, fees, amount),
+        );
+
+        let token_client = TokenClient::new(&env, &stable_token);
+        token_client.transfer(&env.current_contract_address(), &to, &amount);
+
+        env.events()
+            .publish((FEES_WITHDRAWN, stable_token), (to, amount));
+    }
+
+    pub fn get_insurance_fund(env: Env, stable_token: Address) -> InsuranceFund {
+        env.storage()
+            .persistent()
+            .get(&DataKey::InsuranceFund(stable_token))
+            .unwrap_or(InsuranceFund {
+                balance: 0,
+                total_contributed: 0,
+                total_covered: 0,
+            })
+    }
+
+    /// Top up a stable token's insurance fund on top of its share of protocol fees
+    pub fn fund_insurance(env: Env, stable_token: Address, from: Address, amount: i128) {
+        from.require_auth();
+
+        if amount <= 0 {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+        let token_client = TokenClient::new(&env, &stable_token);
+        token_client.transfer(&from, &env.current_contract_address(), &amount);
+        Self::add_to_insurance_fund(&env, &stable_token, amount);
+
+        env.events()
+            .publish((INSURANCE_FUNDED, stable_token), (from, amount));
+    }
+
+    /// Collateral locked by a pool that its liquidity no longer backs
+    pub fn get_pool_shortfall(env: Env, pool_id: u64) -> i128 {
+        let locked = Self::get_pool_locked_collateral(env.clone(), pool_id);
+        (locked - Self::get_pool_total_liquidity(env.clone(), pool_id)).max(0)
+    }
+
+    /// Cover a pool's shortfall from its stable token's insurance fund, leaving any
+    /// remainder with the pool's own LPs. Returns the liquidity restored to the pool.
+    pub fn cover_pool_shortfall(env: Env, pool_id: u64) -> i128 {
+        if Self::get_pool_shortfall(env.clone(), pool_id) == 0 {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+        Self::backstop_shortfall(&env, pool_id)
+    }
+
+    pub fn get_insurance_fund_health(env: Env, stable_token: Address) -> InsuranceFundHealth {
+        let mut locked_collateral = 0i128;
+        let mut open_shortfall = 0i128;
+        for pool_id in Self::get_all_pools(env.clone()).iter() {
+            if Self::get_pool(env.clone(), pool_id).stable_token == stable_token {
+                locked_collateral = add(
+                    &env,
+                    locked_collateral,
+                    Self::get_pool_locked_collateral(env.clone(), pool_id),
+                );
+                open_shortfall = add(
+                    &env,
+                    open_shortfall,
+                    Self::get_pool_shortfall(env.clone(), pool_id),
+                );
+            }
+        }
+
+        let balance = Self::get_insurance_fund(env.clone(), stable_token).balance;
+        let coverage_bps = if balance >= locked_collateral {
+            BPS
+        } else {
+            mul_div_floor(&env, balance, BPS, locked_collateral)
+        };
+        InsuranceFundHealth {
+            balance,
+            locked_collateral,
+            open_shortfall,
+            coverage_bps: coverage_bps as u32,
+        }
+    }
+
+    /// Admin function to designate the account that publishes a pool's vol surface
+    pub fn set_vol_oracle(env: Env, pool_id: u64, oracle: Address) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        Self::get_pool(env.clone(), pool_id);
+        env.storage()
+            .persistent()
+            .set(&DataKey::PoolVolOracle(pool_id), &oracle);
+
+        env.events().publish((VOL_ORACLE_SET, pool_id), oracle);
+    }
+
+    pub fn get_vol_oracle(env: Env, pool_id: u64) -> Option<Address> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolVolOracle(pool_id))
+    }
+
+    /// Vol oracle function to replace a pool's whole implied vol surface. `vols` holds one
+    /// row of `moneyness.len()` points per tenor; every point must lie within 1%-500%.
+    pub fn update_vol_surface(
+        env: Env,
+        pool_id: u64,
+        moneyness: Vec<i128>,
+        tenors: Vec<u64>,
+        vols: Vec<i128>,
+    ) {
+        Self::get_pool(env.clone(), pool_id);
+        let oracle = Self::get_vol_oracle(env.clone(), pool_id)
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::Unauthorized));
+        oracle.require_auth();
+
+        let valid_axes = (1..=MAX_SURFACE_POINTS).contains(&moneyness.len())
+            && (1..=MAX_SURFACE_POINTS).contains(&tenors.len())
+            && vols.len() == moneyness.len() * tenors.len()
+            && moneyness.get_unchecked(0) > 0
+            && tenors.get_unchecked(0) > 0
+            && (1..moneyness.len())
+                .all(|i| moneyness.get_unchecked(i - 1) < moneyness.get_unchecked(i))
+            && (1..tenors.len()).all(|i| tenors.get_unchecked(i - 1) < tenors.get_unchecked(i));
+        if !valid_axes
+            || vols
+                .iter()
+                .any(|v| !(MIN_VOLATILITY..=MAX_VOLATILITY).contains(&v))
+        {
+            panic_with_error!(&env, OptionsError::InvalidVolSurface);
+        }
+
+        let updated_at = env.ledger().timestamp();
+        env.storage().persistent().set(
+            &DataKey::PoolVolSurface(pool_id),
+            &VolSurface {
+                moneyness,
+                tenors,
+                vols,
+                updated_at,
+            },
+        );
+
+        env.events()
+            .publish((VOL_SURFACE_UPDATED, pool_id), updated_at);
+    }
+
+    pub fn get_vol_surface(env: Env, pool_id: u64) -> VolSurface {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolVolSurface(pool_id))
+            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::VolSurfaceNotSet))
+    }
+
+    /// Volatility the premium engine uses for an option on the pool at the current spot,
+    /// interpolated from the vol surface (or the flat pool volatility if none is set)
+    pub fn get_implied_vol(env: Env, pool_id: u64, strike: i128, expiry: u64) -> i128 {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if strike <= 0 {
+            panic_with_error!(&env, OptionsError::InvalidAmount);
+        }
+        let spot = Self::get_price_from_feed(env.clone(), pool.price_feed);
+        let tenor = expiry.saturating_sub(env.ledger().timestamp());
+        Self::model_volatility(&env, pool_id, spot, strike, tenor)
+    }
+
+    // Get all pools (for UI purposes) - returns Vec of pool IDs
+    pub fn get_all_pools(env: Env) -> Vec<u64> {
+        let pool_count = Self::get_pool_counter(env.clone());
+        let mut pools = Vec::new(&env);
+
+        for i in 0..pool_count {
+            if env.storage().persistent().has(&DataKey::Pool(i)) {
+                pools.push_back(i);
+            }
+        }
+
+        pools
+    }
+}
+
+// Internal helpers (not exported as contract functions)
+impl OptionsContract {
+    // Shared purchase path for ad-hoc, series and quoted options; caller handles auth.
+    // Without a quoted premium the option is priced with the model.
+    fn open_option(
+        env: &Env,
+        pool_id: u64,
+        buyer: Address,
+        opt_type: OptionType,
+        strike: i128,
+        expiry: u64,
+        amount: i128,
+        style: OptionStyle,
+        series_id: Option<u64>,
+        settlement: SettlementType,
+        quoted_premium: Option<i128>,
+    ) -> u64 {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        if !pool.is_active {
+            panic_with_error!(env, OptionsError::PoolNotActive);
+        }
+
+        // Perpetuals have no expiry to bound
+        if expiry != PERPETUAL_EXPIRY {
+            Self::require_expiry_in_bounds(env, pool_id, expiry);
+        }
+
+        if amount <= 0 || strike <= 0 {
+            panic_with_error!(env, OptionsError::InvalidAmount);
+        }
+        Self::require_min_trade(env, amount);
+
+        let premium = quoted_premium.unwrap_or_else(|| {
+            Self::model_premium(env, &pool, &opt_type, strike, expiry, amount, true)
+        });
+
+        // Physical calls lock the underlying itself; everything else locks strike value
+        let (collateral_needed, locked_key, unlocked) =
+            if settlement == SettlementType::Physical && opt_type == OptionType::Call {
+                (
+                    Self::underlying_units_ceil(env, &pool, amount),
+                    DataKey::PoolLockedUnderlying(pool_id),
+                    Self::get_pool_underlying_liquidity(env.clone(), pool_id)
+                        - Self::get_pool_locked_underlying(env.clone(), pool_id),
+                )
+            } else {
+                (
+                    value_ceil(env, strike, amount, pool.stable_decimals),
+                    DataKey::PoolLockedCollateral(pool_id),
+                    Self::option_capacity(env, pool_id),
+                )
+            };
+
+        // Check available liquidity in this pool
+        let locked_collateral: i128 = env.storage().persistent().get(&locked_key).unwrap_or(0);
+
+        if unlocked < collateral_needed {
+            panic_with_error!(env, OptionsError::InsufficientLiquidity);
+        }
+
+        // Transfer premium from buyer
+        Self::collect_premium(env, &pool, &buyer, premium);
+
+        // Update locked collateral for this pool
+        env.storage()
+            .persistent()
+            .set(&locked_key, &add(env, locked_collateral, collateral_needed));
+
+        // Create option
+        Self::store_option(
+            env,
+            OptionData {
+                pool_id,
+                buyer,
+                opt_type,
+                strike,
+                expiry,
+                amount,
+                premium_paid: premium,
+                collateral: collateral_needed,
+                is_exercised: false,
+                is_active: true,
+                style,
+                series_id,
+                settlement,
+                writer: OptionWriter::Pool,
+                barrier: OptionBarrier::NoBarrier,
+                payoff: OptionPayoff::Vanilla,
+            },
+        )
+    }
+
+    // Premium model: Black-76 at the oracle spot and the pool's interpolated implied vol,
+    // or the pool's flat premium_bps of the strike notional while it has no vol surface.
+    // The pool charges the premium rounded up and pays it rounded down.
+    fn model_premium(
+        env: &Env,
+        pool: &PoolData,
+        opt_type: &OptionType,
+        strike: i128,
+        expiry: u64,
+        amount: i128,
+        round_up: bool,
+    ) -> i128 {
+        let has_surface = env
+            .storage()
+            .persistent()
+            .has(&DataKey::PoolVolSurface(pool.pool_id));
+        if !has_surface {
+            let premium_bps = Self::get_pool_params(env.clone(), pool.pool_id).premium_bps as i128;
+            return if round_up {
+                let notional = value_ceil(env, strike, amount, pool.stable_decimals);
+                mul_div_ceil(env, notional, premium_bps, BPS)
+            } else {
+                let notional = value_floor(env, strike, amount, pool.stable_decimals);
+                mul_div_floor(env, notional, premium_bps, BPS)
+            };
+        }
+
+        let spot = Self::get_price_from_feed(env.clone(), pool.price_feed.clone());
+        let tenor = expiry.saturating_sub(env.ledger().timestamp());
+        let vol = Self::model_volatility(env, pool.pool_id, spot, strike, tenor);
+        let years = pricing::years_until(env, 0, tenor);
+        let variance = mul_div_floor(env, mul_div_floor(env, vol, vol, SCALE), years, SCALE);
+        let unit_price =
+            pricing::black_price(env, spot, strike, variance, *opt_type == OptionType::Call);
+        if round_up {
+            value_ceil(env, unit_price, amount, pool.stable_decimals)
+        } else {
+            value_floor(env, unit_price, amount, pool.stable_decimals)
+        }
+    }
+
+    // Take the premium for an option the pool writes: the premium is booked to the pool
+    // and the protocol fee charged on top is set aside for the stable token, less the
+    // insurance fund's share
+    fn collect_premium(env: &Env, pool: &PoolData, buyer: &Address, premium: i128) {
+        let fee = Self::protocol_fee(env, pool.pool_id, premium);
+        let insurance = mul_div_floor(env, fee, INSURANCE_FEE_SHARE_BPS, BPS);
+
+        let token_client = TokenClient::new(env, &pool.stable_token);
+        token_client.transfer(
+            buyer,
+            &env.current_contract_address(),
+            &add(env, premium, fee),
+        );
+        Self::adjust_pool_liquidity(env, pool.pool_id, premium);
+
+        if fee > insurance {
+            let fees = Self::get_protocol_fees(env.clone(), pool.stable_token.clone());
+            env.storage().persistent().set(
+                &DataKey::ProtocolFees(pool.stable_token.clone()),
+                &add(env, fees, fee - insurance),
+            );
+        }
+        if insurance > 0 {
+            Self::add_to_insurance_fund(env, &pool.stable_token, insurance);
+        }
+    }
+
+    fn add_to_insurance_fund(env: &Env, stable_token: &Address, amount: i128) {
+        let mut fund = Self::get_insurance_fund(env.clone(), stable_token.clone());
+        fund.balance = add(env, fund.balance, amount);
+        fund.total_contributed = add(env, fund.total_contributed, amount);
+        env.storage()
+            .persistent()
+            .set(&DataKey::InsuranceFund(stable_token.clone()), &fund);
+    }
+
+    // Restore a pool's shortfall from the insurance fund. What the fund cannot cover is
+    // left with the pool's own LPs. Returns the liquidity restored.
+    fn backstop_shortfall(env: &Env, pool_id: u64) -> i128 {
+        let shortfall = Self::get_pool_shortfall(env.clone(), pool_id);
+        if shortfall == 0 {
+            return 0;
+        }
+        let pool = Self::get_pool(env.clone(), pool_id);
+        let mut fund = Self::get_insurance_fund(env.clone(), pool.stable_token.clone());
+
+        let covered = shortfall.min(fund.balance);
+        fund.balance = sub(env, fund.balance, covered);
+        fund.total_covered = add(env, fund.total_covered, covered);
+        if covered > 0 {
+            env.events()
+                .publish((SHORTFALL_COVERED, pool_id), (covered, fund.balance));
+        }
+        if covered < shortfall {
+            env.events()
+                .publish((LP_LOSS, pool_id), shortfall - covered);
+        }
+
+        env.storage()
+            .persistent()
+            .set(&DataKey::InsuranceFund(pool.stable_token), &fund);
+        Self::adjust_pool_liquidity(env, pool_id, covered);
+        covered
+    }
+
+    fn protocol_fee(env: &Env, pool_id: u64, premium: i128) -> i128 {
+        let fee_bps = Self::get_pool_params(env.clone(), pool_id).fee_bps;
+        mul_div_ceil(env, premium, fee_bps as i128, BPS)
+    }
+
+    // Quote a cash-settled option from one pool, or None if the pool is not taking new
+    // options at this expiry
+    fn pool_quote(
+        env: &Env,
+        pool_id: u64,
+        opt_type: &OptionType,
+        strike: i128,
+        expiry: u64,
+        amount: i128,
+    ) -> Option<PoolQuote> {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        let params = Self::get_pool_params(env.clone(), pool_id);
+        let tenor = expiry - env.ledger().timestamp();
+        if !pool.is_active || tenor < params.min_expiry || tenor > params.max_expiry {
+            return None;
+        }
+
+        let premium = Self::model_premium(env, &pool, opt_type, strike, expiry, amount, true);
+        Some(PoolQuote {
+            pool_id,
+            premium,
+            fee: Self::protocol_fee(env, pool_id, premium),
+            collateral: value_ceil(env, strike, amount, pool.stable_decimals),
+            capacity: Self::option_capacity(env, pool_id),
+        })
+    }
+
+    // Cheapest quote, by premium plus fee, whose pool can lock the full collateral
+    fn cheapest_full_fill(quotes: &Vec<PoolQuote>) -> Option<PoolQuote> {
+        let mut best: Option<PoolQuote> = None;
+        for quote in quotes.iter() {
+            if quote.capacity < quote.collateral {
+                continue;
+            }
+            let cheaper = match &best {
+                Some(current) => quote.premium + quote.fee < current.premium + current.fee,
+                None => true,
+            };
+            if cheaper {
+                best = Some(quote);
+            }
+        }
+        best
+    }
+
+    // Split an order over quoted pools as (pool_id, amount): all of it to the cheapest pool
+    // that can write it alone, otherwise (if allowed) as much as each pool's capacity takes,
+    // cheapest first, skipping fills under the minimum trade
+    fn route_fills(
+        env: &Env,
+        quotes: &Vec<PoolQuote>,
+        amount: i128,
+        allow_split: bool,
+    ) -> Vec<(u64, i128)> {
+        if let Some(quote) = Self::cheapest_full_fill(quotes) {
+            return Vec::from_array(env, [(quote.pool_id, amount)]);
+        }
+        if !allow_split {
+            panic_with_error!(env, OptionsError::InsufficientLiquidity);
+        }
+
+        // Quotes are for the full amount and premiums are linear in it, so the cheapest
+        // quote also has the cheapest unit price
+        let mut fills = Vec::new(env);
+        let mut remaining = quotes.clone();
+        let mut unfilled = amount;
+        while unfilled > 0 && !remaining.is_empty() {
+            let mut cheapest = 0;
+            for (index, quote) in remaining.iter().enumerate() {
+                let best = remaining.get_unchecked(cheapest);
+                if quote.premium + quote.fee < best.premium + best.fee {
+                    cheapest = index as u32;
+                }
+            }
+            let quote = remaining.get_unchecked(cheapest);
+            remaining.remove(cheapest);
+
+            // Collateral is linear in amount, so this fill's collateral fits the capacity
+            let fill = mul_div_floor(env, amount, quote.capacity, quote.collateral).min(unfilled);
+            if fill >= MIN_OPTION_AMOUNT {
+                fills.push_back((quote.pool_id, fill));
+                unfilled = sub(env, unfilled, fill);
+            }
+        }
+
+        if unfilled > 0 {
+            panic_with_error!(env, OptionsError::InsufficientLiquidity);
+        }
+        fills
+    }
+
+    fn validate_pool_params(env: &Env, params: &PoolParams) {
+        let valid = (MIN_VOLATILITY..=MAX_VOLATILITY).contains(&params.volatility)
+            && params.premium_bps as i128 <= BPS
+            && params.fee_bps <= MAX_FEE_BPS
+            && params.max_utilization_bps > 0
+            && params.max_utilization_bps as i128 <= BPS
+            && params.min_expiry < params.max_expiry;
+        if !valid {
+            panic_with_error!(env, OptionsError::InvalidPoolParams);
+        }
+    }
+
+    fn apply_pool_params(env: &Env, pool_id: u64, params: &PoolParams) {
+        let mut pool = Self::get_pool(env.clone(), pool_id);
+        pool.price_feed = params.price_feed.clone();
+        env.storage()
+            .persistent()
+            .set(&DataKey::Pool(pool_id), &pool);
+        env.storage()
+            .persistent()
+            .set(&DataKey::PoolParams(pool_id), params);
+
+        env.events()
+            .publish((PARAMS_EXECUTED, pool_id), params.clone());
+    }
+
+    // Store an LP's share balance and checkpoint it for governance snapshots
+    fn set_lp_shares(env: &Env, pool_id: u64, provider: &Address, shares: i128) {
+        env.storage()
+            .persistent()
+            .set(&DataKey::PoolLpShares(pool_id, provider.clone()), &shares);
+
+        let key = DataKey::LpShareCheckpoints(pool_id, provider.clone());
+        let mut checkpoints: Vec<ShareCheckpoint> = env
+            .storage()
+            .persistent()
+            .get(&key)
+            .unwrap_or(Vec::new(env));
+        let ledger = env.ledger().sequence();
+        let checkpoint = ShareCheckpoint { ledger, shares };
+        match checkpoints.last() {
+            Some(last) if last.ledger == ledger => {
+                checkpoints.set(checkpoints.len() - 1, checkpoint);
+            }
+            _ => checkpoints.push_back(checkpoint),
+        }
+        env.storage().persistent().set(&key, &checkpoints);
+    }
+
+    fn require_expiry_in_bounds(env: &Env, pool_id: u64, expiry: u64) {
+        let now = env.ledger().timestamp();
+        if expiry <= now {
+            panic_with_error!(env, OptionsError::OptionExpired);
+        }
+        let params = Self::get_pool_params(env.clone(), pool_id);
+        if expiry - now < params.min_expiry || expiry - now > params.max_expiry {
+            panic_with_error!(env, OptionsError::ExpiryOutOfBounds);
+        }
+    }
+
+    // Option amounts are scaled 1e7; these convert them into underlying token units
+    fn underlying_units_floor(env: &Env, pool: &PoolData, amount: i128) -> i128 {
+        convert_floor(env, amount, 1, 1, SCALE_DECIMALS, pool.underlying_decimals)
+    }
+
+    fn underlying_units_ceil(env: &Env, pool: &PoolData, amount: i128) -> i128 {
+        convert_ceil(env, amount, 1, 1, SCALE_DECIMALS, pool.underlying_decimals)
+    }
+
+    // A 1e7-scaled token amount in units of a token with `decimals` decimals, at least 1
+    fn token_units(env: &Env, amount: i128, decimals: u32) -> i128 {
+        convert_floor(env, amount, 1, 1, SCALE_DECIMALS, decimals).max(1)
+    }
+
+    fn require_min_trade(env: &Env, amount: i128) {
+        if amount < MIN_OPTION_AMOUNT {
+            panic_with_error!(env, OptionsError::TradeTooSmall);
+        }
+    }
+
+    // Shares for an LP deposit of a token with `decimals` decimals, as (credited to
+    // provider, added to total supply). The first deposit also mints DEAD_SHARES that
+    // nobody can redeem. They are minted here rather than when the pool is created:
+    // shares minted against an empty pool would leave nothing to price the next deposit.
+    // Deposits into a pool whose shares are backed by no value are rejected.
+    fn deposit_shares(
+        env: &Env,
+        amount: i128,
+        total_liquidity: i128,
+        total_shares: i128,
+        min_shares_out: i128,
+        decimals: u32,
+    ) -> (i128, i128) {
+        if amount < Self::token_units(env, MIN_DEPOSIT, decimals) {
+            panic_with_error!(env, OptionsError::DepositTooSmall);
+        }
+
+        let (shares, minted) = if total_shares == 0 {
+            (
+                amount - Self::token_units(env, DEAD_SHARES, decimals),
+                amount,
+            )
+        } else if total_liquidity <= 0 {
+            panic_with_error!(env, OptionsError::InsufficientLiquidity);
+        } else {
+            let shares = mul_div_floor(env, amount, total_shares, total_liquidity);
+            (shares, shares)
+        };
+
+        if shares <= 0 {
+            panic_with_error!(env, OptionsError::DepositTooSmall);
+        }
+        if shares < min_shares_out {
+            panic_with_error!(env, OptionsError::SlippageExceeded);
+        }
+
+        (shares, minted)
+    }
+
+    // Value of shares escrowed in the withdrawal queue at the book share price, which
+    // reserves at least their NAV value without marking every open option
+    fn queued_withdrawal_value(env: &Env, pool_id: u64) -> i128 {
+        let queued = Self::get_pool_queued_shares(env.clone(), pool_id);
+        if queued == 0 {
+            return 0;
+        }
+        mul_div_ceil(
+            env,
+            queued,
+            Self::get_pool_total_liquidity(env.clone(), pool_id),
+            Self::get_pool_total_lp_shares(env.clone(), pool_id),
+        )
+    }
+
+    // Stable liquidity not locked as option collateral; queued withdrawals are paid from it
+    fn unlocked_liquidity(env: &Env, pool_id: u64) -> i128 {
+        let unlocked = Self::get_pool_total_liquidity(env.clone(), pool_id)
+            - Self::get_pool_locked_collateral(env.clone(), pool_id);
+        unlocked.max(0)
+    }
+
+    // Stable liquidity that is neither locked as collateral nor reserved for the queue
+    fn free_liquidity(env: &Env, pool_id: u64) -> i128 {
+        (Self::unlocked_liquidity(env, pool_id) - Self::queued_withdrawal_value(env, pool_id))
+            .max(0)
+    }
+
+    // Free liquidity new options may lock without exceeding the pool's utilization cap
+    fn option_capacity(env: &Env, pool_id: u64) -> i128 {
+        let max_utilization_bps = Self::get_pool_params(env.clone(), pool_id).max_utilization_bps;
+        let max_locked = mul_div_floor(
+            env,
+            Self::get_pool_total_liquidity(env.clone(), pool_id),
+            max_utilization_bps as i128,
+            BPS,
+        );
+        let headroom = max_locked - Self::get_pool_locked_collateral(env.clone(), pool_id);
+        Self::free_liquidity(env, pool_id).min(headroom.max(0))
+    }
+
+    // Burn pool shares already taken from the provider's balance and pay out their
+    // stable value at `nav` (see stable_nav) plus any underlying delivered by physical puts
+    fn redeem_lp_shares(
+        env: &Env,
+        pool: &PoolData,
+        provider: &Address,
+        share_amount: i128,
+        nav: i128,
+    ) -> i128 {
+        let pool_id = pool.pool_id;
+        let total_liquidity = Self::get_pool_total_liquidity(env.clone(), pool_id);
+        let total_lp_shares = Self::get_pool_total_lp_shares(env.clone(), pool_id);
+        let delivered = Self::get_pool_delivered_underlying(env.clone(), pool_id);
+
+        let pool_portion = mul_div_floor(env, share_amount, nav.max(0), total_lp_shares);
+        let underlying_portion = mul_div_floor(env, share_amount, delivered, total_lp_shares);
+
+        env.storage().persistent().set(
+            &DataKey::PoolTotalLpShares(pool_id),
+            &sub(env, total_lp_shares, share_amount),
+        );
+        env.storage().persistent().set(
+            &DataKey::PoolTotalLiquidity(pool_id),
+            &sub(env, total_liquidity, pool_portion),
+        );
+
+        let token_client = TokenClient::new(env, &pool.stable_token);
+        token_client.transfer(&env.current_contract_address(), provider, &pool_portion);
+
+        if underlying_portion > 0 {
+            env.storage().persistent().set(
+                &DataKey::PoolDeliveredUnderlying(pool_id),
+                &sub(env, delivered, underlying_portion),
+            );
+            let underlying_client = TokenClient::new(env, &pool.underlying_asset);
+            underlying_client.transfer(
+                &env.current_contract_address(),
+                provider,
+                &underlying_portion,
+            );
+        }
+
+        pool_portion
+    }
+
+    // Persist a new option under the next ID and announce the purchase
+    fn store_option(env: &Env, option: OptionData) -> u64 {
+        let option_id = Self::get_option_counter(env.clone());
+
+        env.storage()
+            .persistent()
+            .set(&DataKey::Option(option_id), &option);
+        env.storage()
+            .instance()
+            .set(&DataKey::OptionCounter, &(option_id + 1));
+
+        if option.writer == OptionWriter::Pool {
+            let mut open_options = Self::claim_open_option_slot(env, &option);
+            open_options.push_back(option_id);
+            env.storage()
+                .persistent()
+                .set(&DataKey::PoolOpenOptions(option.pool_id), &open_options);
+        }
+
+        // Emit event
+        env.events().publish(
+            (OPTION_PURCHASED, option.buyer),
+            (
+                option_id,
+                option.pool_id,
+                option.opt_type,
+                option.strike,
+                option.expiry,
+                option.amount,
+                option.premium_paid,
+                option.collateral,
+            ),
+        );
+
+        option_id
+    }
+
+    // Make room for a new pool-written option whose collateral is already locked; returns
+    // the pool's open options. A full list is first swept of options past their deadline.
+    fn claim_open_option_slot(env: &Env, option: &OptionData) -> Vec<u64> {
+        let pool_id = option.pool_id;
+        let mut open_options = Self::get_pool_open_options(env.clone(), pool_id);
+        if open_options.len() >= MAX_POOL_OPEN_OPTIONS {
+            let now = env.ledger().timestamp();
+            for option_id in open_options.iter() {
+                let open = Self::get_option(env.clone(), option_id);
+                if now > Self::exercise_deadline(&open) {
+                    Self::close_expired(env, option_id, open);
+                }
+            }
+            open_options = Self::get_pool_open_options(env.clone(), pool_id);
+        }
+        let capacity_left = if option.settlement == SettlementType::Physical
+            && option.opt_type == OptionType::Call
+        {
+            Self::get_pool_underlying_liquidity(env.clone(), pool_id)
+                - Self::get_pool_locked_underlying(env.clone(), pool_id)
+        } else {
+            Self::option_capacity(env, pool_id)
+        };
+        Self::require_open_slot(
+            env,
+            open_options.len(),
+            MAX_POOL_OPEN_OPTIONS,
+            option.collateral,
+            capacity_left,
+        );
+        open_options
+    }
+
+    // Check a new position fits a list of `open` that NAV marks reprice, holding at most
+    // `max`. Once the list is half full, the position must lock at least the capacity left
+    // (counting its own collateral) divided by the slots left.
+    fn require_open_slot(env: &Env, open: u32, max: u32, collateral: i128, capacity_left: i128) {
+        if open >= max {
+            panic_with_error!(env, OptionsError::TooManyOpenOptions);
+        }
+        if open >= max / 2 {
+            let slots_left = (max - open) as i128;
+            if mul(env, collateral, slots_left - 1) < capacity_left {
+                panic_with_error!(env, OptionsError::TradeTooSmall);
+            }
+        }
+    }
+
+    // Mark an option expired and free its collateral
+    fn close_expired(env: &Env, option_id: u64, mut option: OptionData) {
+        option.is_active = false;
+        option.is_exercised = false;
+        env.storage()
+            .persistent()
+            .set(&DataKey::Option(option_id), &option);
+
+        // Free collateral from the writer
+        Self::release_collateral(env, option_id, &option);
+        if let OptionWriter::Account(_) = option.writer {
+            let pool = Self::get_pool(env.clone(), option.pool_id);
+            Self::refund_writer(env, &option, &pool, 0);
+        }
+        if let Some(series_id) = option.series_id {
+            Self::update_open_interest(env, series_id, -option.amount);
+        }
+
+        env.events()
+            .publish((OPTION_EXPIRED, option.buyer), option_id);
+    }
+
+    // (deposit token, proceeds token) of a vault
+    fn vault_tokens(vault: &VaultData, pool: &PoolData) -> (Address, Address) {
+        match vault.strategy {
+            VaultStrategy::CoveredCall => {
+                (pool.underlying_asset.clone(), pool.stable_token.clone())
+            }
+            VaultStrategy::CashSecuredPut => {
+                (pool.stable_token.clone(), pool.underlying_asset.clone())
+            }
+        }
+    }
+
+    fn get_vault_epoch_options(env: &Env, vault_id: u64) -> Vec<u64> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::VaultEpochOptions(vault_id))
+            .unwrap_or(Vec::new(env))
+    }
+
+    // Pay out an exercise at the current oracle price; the caller has already authorized it
+    fn settle_exercise(env: &Env, option_id: u64, mut option: OptionData) -> i128 {
+        let now = env.ledger().timestamp();
+        if option.style == OptionStyle::European && now < option.expiry {
+            panic_with_error!(env, OptionsError::NotExercisable);
+        }
+        if now > Self::exercise_deadline(&option) {
+            panic_with_error!(env, OptionsError::OptionExpired);
+        }
+
+        let pool = Self::get_pool(env.clone(), option.pool_id);
+
+        // Crossings nobody triggered yet still count at exercise. A knock-out's history
+        // must be checked up to now, so long gaps have to be paged through with
+        // trigger_barrier first.
+        if let Some(barrier) = option.barrier.get().filter(|barrier| !barrier.is_crossed) {
+            let (crossing, scanned_to) = Self::find_barrier_crossing(env, &pool, &option, &barrier);
+            match (barrier.kind, crossing) {
+                (BarrierKind::KnockOut, Some(crossing)) => {
+                    Self::knock_out(env, option_id, option, crossing);
+                    return 0;
+                }
+                (BarrierKind::KnockOut, None) if scanned_to < now.min(option.expiry) => {
+                    panic_with_error!(env, OptionsError::NotExercisable);
+                }
+                (BarrierKind::KnockIn, None) => {
+                    panic_with_error!(env, OptionsError::BarrierNotCrossed);
+                }
+                _ => {}
+            }
+        }
+
+        if option.settlement == SettlementType::Physical {
+            return Self::settle_physical(env, option_id, option, &pool);
+        }
+
+        let mut payoff = 0i128;
+
+        // Calculate payoff
+        if let OptionPayoff::CashOrNothing(_) = option.payoff {
+            payoff = Self::digital_payoff(env, &pool, &option);
+        } else {
+            // Asian options settle on the average over their window and other Europeans on
+            // the last print at or before expiry, however late in the window they exercise
+            let settlement_price = match &option.payoff {
+                OptionPayoff::Average(averaging) => {
+                    Self::average_price(env, &pool, option.expiry, averaging)
+                }
+                _ if option.style == OptionStyle::European => {
+                    Self::expiry_price(env, &pool, option.expiry)
+                }
+                _ => Self::get_price_from_feed(env.clone(), pool.price_feed.clone()),
+            };
+            match option.opt_type {
+                OptionType::Call => {
+                    if settlement_price > option.strike {
+                        payoff = value_floor(
+                            env,
+                            settlement_price - option.strike,
+                            option.amount,
+                            pool.stable_decimals,
+                        );
+                    }
+                }
+                OptionType::Put => {
+                    if settlement_price < option.strike {
+                        payoff = value_floor(
+                            env,
+                            option.strike - settlement_price,
+                            option.amount,
+                            pool.stable_decimals,
+                        );
+                    }
+                }
+            }
+        }
+
+        // Perpetuals close their funding account; funding left unpaid comes out of the payoff
+        if option.expiry == PERPETUAL_EXPIRY {
+            payoff = Self::close_funding(env, option_id, &option, &pool, payoff);
+        }
+
+        // Update option status
+        option.is_active = false;
+        option.is_exercised = true;
+        env.storage()
+            .persistent()
+            .set(&DataKey::Option(option_id), &option);
+
+        // Unlock the writer's collateral
+        Self::release_collateral(env, option_id, &option);
+        if let Some(series_id) = option.series_id {
+            Self::update_open_interest(env, series_id, -option.amount);
+        }
+
+        // Transfer payoff if any
+        if payoff > 0 {
+            let actual_payoff = if payoff > option.collateral {
+                option.collateral
+            } else {
+                payoff
+            };
+            let token_client = TokenClient::new(env, &pool.stable_token);
+            token_client.transfer(
+                &env.current_contract_address(),
+                &option.buyer,
+                &actual_payoff,
+            );
+            if option.writer == OptionWriter::Pool {
+                Self::adjust_pool_liquidity(env, option.pool_id, -actual_payoff);
+                Self::backstop_shortfall(env, option.pool_id);
+            }
+            Self::refund_writer(env, &option, &pool, actual_payoff);
+
+            env.events()
+                .publish((OPTION_EXERCISED, option.buyer), (option_id, actual_payoff));
+            actual_payoff
+        } else {
+            Self::refund_writer(env, &option, &pool, 0);
+            env.events()
+                .publish((OPTION_EXERCISED, option.buyer), (option_id, 0i128));
+            0
+        }
+    }
+
+    // Last moment an option can be exercised
+    fn exercise_deadline(option: &OptionData) -> u64 {
+        match option.style {
+            OptionStyle::American => option.expiry,
+            OptionStyle::European => option.expiry + EUROPEAN_EXERCISE_WINDOW,
+        }
+    }
+
+    // Register a new series; returns None if an identical series is already listed
+    fn create_series(
+        env: &Env,
+        pool_id: u64,
+        opt_type: OptionType,
+        strike: i128,
+        expiry: u64,
+        style: OptionStyle,
+    ) -> Option<u64> {
+        if expiry <= env.ledger().timestamp() {
+            panic_with_error!(env, OptionsError::OptionExpired);
+        }
+        if strike <= 0 {
+            panic_with_error!(env, OptionsError::InvalidAmount);
+        }
+
+        let key = DataKey::SeriesExists(pool_id, opt_type.clone(), strike, expiry, style.clone());
+        if env.storage().persistent().has(&key) {
+            return None;
+        }
+
+        let series_id = Self::get_series_counter(env.clone());
+        let series = SeriesData {
+            series_id,
+            pool_id,
+            opt_type: opt_type.clone(),
+            strike,
+            expiry,
+            style: style.clone(),
+            open_interest: 0,
+            is_archived: false,
+        };
+
+        env.storage()
+            .persistent()
+            .set(&DataKey::Series(series_id), &series);
+        env.storage().persistent().set(&key, &series_id);
+
+        let mut listed = Self::get_pool_series(env.clone(), pool_id);
+        listed.push_back(series_id);
+        env.storage()
+            .persistent()
+            .set(&DataKey::PoolSeries(pool_id), &listed);
+
+        env.storage()
+            .instance()
+            .set(&DataKey::SeriesCounter, &(series_id + 1));
+
+        env.events().publish(
+            (SERIES_LISTED, pool_id),
+            (series_id, opt_type, strike, expiry, style),
+        );
+
+        Some(series_id)
+    }
+
+    // Move expired series from the listed set to the archive
+    fn archive_series(env: &Env, pool_id: u64) -> u32 {
+        let now = env.ledger().timestamp();
+        let listed = Self::get_pool_series(env.clone(), pool_id);
+        let mut still_listed = Vec::new(env);
+        let mut archived = Self::get_pool_archived_series(env.clone(), pool_id);
+        let mut count = 0u32;
+
+        for series_id in listed.iter() {
+            let mut series = Self::get_series(env.clone(), series_id);
+            if series.expiry > now {
+                still_listed.push_back(series_id);
+                continue;
+            }
+
+            series.is_archived = true;
+            env.storage()
+                .persistent()
+                .set(&DataKey::Series(series_id), &series);
+            archived.push_back(series_id);
+            count += 1;
+
+            env.events().publish((SERIES_ARCHIVED, pool_id), series_id);
+        }
+
+        if count > 0 {
+            env.storage()
+                .persistent()
+                .set(&DataKey::PoolSeries(pool_id), &still_listed);
+            env.storage()
+                .persistent()
+                .set(&DataKey::PoolArchivedSeries(pool_id), &archived);
+        }
+
+        count
+    }
+
+    // Unlock an option's collateral from whichever pool sleeve backs it
+    fn release_collateral(env: &Env, option_id: u64, option: &OptionData) {
+        match option.writer {
+            OptionWriter::Vault(vault_id) => {
+                let mut vault = Self::get_vault(env.clone(), vault_id);
+                vault.locked = sub(env, vault.locked, option.collateral);
+                env.storage()
+                    .persistent()
+                    .set(&DataKey::Vault(vault_id), &vault);
+                return;
+            }
+            // Escrowed outside the pool; returned by refund_writer
+            OptionWriter::Account(_) => return,
+            OptionWriter::Pool => {}
+        }
+
+        let mut open_options = Self::get_pool_open_options(env.clone(), option.pool_id);
+        if let Some(index) = open_options.first_index_of(option_id) {
+            open_options.remove(index);
+            env.storage()
+                .persistent()
+                .set(&DataKey::PoolOpenOptions(option.pool_id), &open_options);
+        }
+
+        let locked_key = if option.settlement == SettlementType::Physical
+            && option.opt_type == OptionType::Call
+        {
+            DataKey::PoolLockedUnderlying(option.pool_id)
+        } else {
+            DataKey::PoolLockedCollateral(option.pool_id)
+        };
+        let locked: i128 = env.storage().persistent().get(&locked_key).unwrap_or(0);
+        env.storage()
+            .persistent()
+            .set(&locked_key, &sub(env, locked, option.collateral));
+    }
+
+    // Physical exercise: calls swap strike for underlying, puts swap underlying for strike.
+    // Returns the amount delivered to the holder.
+    fn settle_physical(env: &Env, option_id: u64, mut option: OptionData, pool: &PoolData) -> i128 {
+        let contract = env.current_contract_address();
+        let stable_client = TokenClient::new(env, &pool.stable_token);
+        let underlying_client = TokenClient::new(env, &pool.underlying_asset);
+
+        // Europeans can only take delivery if they finished in the money at expiry
+        if option.style == OptionStyle::European {
+            let settlement_price = Self::expiry_price(env, pool, option.expiry);
+            let in_the_money = match option.opt_type {
+                OptionType::Call => settlement_price > option.strike,
+                OptionType::Put => settlement_price < option.strike,
+            };
+            if !in_the_money {
+                panic_with_error!(env, OptionsError::NotInTheMoney);
+            }
+        }
+
+        // Holders pay rounded up and receive rounded down
+        let (strike_value, underlying_amount) = match option.opt_type {
+            OptionType::Call => (
+                value_ceil(env, option.strike, option.amount, pool.stable_decimals),
+                Self::underlying_units_floor(env, pool, option.amount),
+            ),
+            OptionType::Put => (
+                value_floor(env, option.strike, option.amount, pool.stable_decimals),
+                Self::underlying_units_ceil(env, pool, option.amount),
+            ),
+        };
+        option.is_active = false;
+        option.is_exercised = true;
+        env.storage()
+            .persistent()
+            .set(&DataKey::Option(option_id), &option);
+
+        Self::release_collateral(env, option_id, &option);
+        if let Some(series_id) = option.series_id {
+            Self::update_open_interest(env, series_id, -option.amount);
+        }
+
+        let delivered = match option.opt_type {
+            OptionType::Call => {
+                stable_client.transfer(&option.buyer, &contract, &strike_value);
+                underlying_client.transfer(&contract, &option.buyer, &underlying_amount);
+                underlying_amount
+            }
+            OptionType::Put => {
+                underlying_client.transfer(&option.buyer, &contract, &underlying_amount);
+                stable_client.transfer(&contract, &option.buyer, &strike_value);
+                strike_value
+            }
+        };
+
+        match (option.writer.clone(), option.opt_type.clone()) {
+            // Vault writers give up collateral and keep what the holder paid in
+            (OptionWriter::Vault(vault_id), _) => {
+                let received = if option.opt_type == OptionType::Call {
+                    strike_value
+                } else {
+                    underlying_amount
+                };
+                let mut vault = Self::get_vault(env.clone(), vault_id);
+                vault.total_assets = sub(env, vault.total_assets, delivered);
+                vault.total_proceeds = add(env, vault.total_proceeds, received);
+                env.storage()
+                    .persistent()
+                    .set(&DataKey::Vault(vault_id), &vault);
+            }
+            (OptionWriter::Pool, OptionType::Call) => {
+                let underlying = Self::get_pool_underlying_liquidity(env.clone(), option.pool_id);
+                let proceeds = Self::get_pool_underlying_proceeds(env.clone(), option.pool_id);
+                env.storage().persistent().set(
+                    &DataKey::PoolUnderlyingLiquidity(option.pool_id),
+                    &sub(env, underlying, underlying_amount),
+                );
+                env.storage().persistent().set(
+                    &DataKey::PoolUnderlyingProceeds(option.pool_id),
+                    &add(env, proceeds, strike_value),
+                );
+            }
+            // Account writers only write cash-settled options
+            (OptionWriter::Account(_), _) => {}
+            (OptionWriter::Pool, OptionType::Put) => {
+                let liquidity = Self::get_pool_total_liquidity(env.clone(), option.pool_id);
+                let delivered_underlying =
+                    Self::get_pool_delivered_underlying(env.clone(), option.pool_id);
+                env.storage().persistent().set(
+                    &DataKey::PoolTotalLiquidity(option.pool_id),
+                    &sub(env, liquidity, strike_value),
+                );
+                env.storage().persistent().set(
+                    &DataKey::PoolDeliveredUnderlying(option.pool_id),
+                    &add(env, delivered_underlying, underlying_amount),
+                );
+            }
+        }
+
+        env.events()
+            .publish((OPTION_EXERCISED, option.buyer), (option_id, delivered));
+        delivered
+    }
+
+    // Owner's combined payoff at `price` in stable units, positive when the pool pays.
+    // Rounds each leg in the owner's favor when `favor_owner` is set, else the pool's.
+    fn strategy_payoff(
+        env: &Env,
+        pool: &PoolData,
+        legs: &Vec<StrategyLeg>,
+        price: i128,
+        favor_owner: bool,
+    ) -> i128 {
+        let mut payoff = 0i128;
+        for leg in legs.iter() {
+            payoff = add(
+                env,
+                payoff,
+                Self::leg_payoff(env, pool, &leg, price, favor_owner),
+            );
+        }
+        payoff
+    }
+
+    // Owner's payoff from one leg at `price`, rounded as in strategy_payoff
+    fn leg_payoff(
+        env: &Env,
+        pool: &PoolData,
+        leg: &StrategyLeg,
+        price: i128,
+        favor_owner: bool,
+    ) -> i128 {
+        let intrinsic = match leg.opt_type {
+            OptionType::Call => (price - leg.strike).max(0),
+            OptionType::Put => (leg.strike - price).max(0),
+        };
+        let round_up = favor_owner == (leg.side == PositionSide::Long);
+        let value = if round_up {
+            value_ceil(env, intrinsic, leg.amount, pool.stable_decimals)
+        } else {
+            value_floor(env, intrinsic, leg.amount, pool.stable_decimals)
+        };
+        match leg.side {
+            PositionSide::Long => value,
+            PositionSide::Short => -value,
+        }
+    }
+
+    // Settle the strategy's expired legs at their expiry prices; long legs settle with no
+    // payoff once their settlement window has lapsed. Returns the updated strategy and the
+    // number of legs settled now and still open.
+    fn settle_expired_legs(
+        env: &Env,
+        pool: &PoolData,
+        mut strategy: StrategyData,
+    ) -> (StrategyData, u32, u32) {
+        let now = env.ledger().timestamp();
+        let (mut settled, mut open) = (0u32, 0u32);
+        for index in 0..strategy.legs.len() {
+            let key = DataKey::StrategyLeg(strategy.strategy_id, index);
+            let record: StrategyLegRecord = env.storage().persistent().get(&key).unwrap();
+            if record.is_settled {
+                continue;
+            }
+            if now < record.leg.expiry {
+                open += 1;
+                continue;
+            }
+
+            // Letting the window pass forfeits what the owner is owed, not what they owe
+            let lapsed = now > record.leg.expiry + EUROPEAN_EXERCISE_WINDOW;
+            let price = if lapsed && record.leg.side == PositionSide::Long {
+                0
+            } else {
+                Self::expiry_price(env, pool, record.leg.expiry)
+            };
+            let payoff =
+                Self::settle_strategy_leg(env, pool, &strategy.owner, index, record, price);
+            strategy.settled_payoff = add(env, strategy.settled_payoff, payoff);
+            settled += 1;
+        }
+        (strategy, settled, open)
+    }
+
+    // Record one leg's settlement at `price` (0 for a lapsed leg, which pays nothing)
+    fn settle_strategy_leg(
+        env: &Env,
+        pool: &PoolData,
+        owner: &Address,
+        index: u32,
+        mut record: StrategyLegRecord,
+        price: i128,
+    ) -> i128 {
+        let payoff = if price > 0 {
+            Self::leg_payoff(env, pool, &record.leg, price, false)
+        } else {
+            0
+        };
+        record.settlement_price = price;
+        record.payoff = payoff;
+        record.is_settled = true;
+        env.storage()
+            .persistent()
+            .set(&DataKey::StrategyLeg(record.strategy_id, index), &record);
+
+        env.events().publish(
+            (STRATEGY_LEG_SETTLED, owner.clone()),
+            (record.strategy_id, index, price, payoff),
+        );
+        payoff
+    }
+
+    // Pay out a strategy whose legs have all settled and release its collateral
+    fn pay_out_strategy(env: &Env, pool: &PoolData, mut strategy: StrategyData) -> i128 {
+        // Payoffs are capped by what each side locked, like single-leg options
+        let payoff = strategy
+            .settled_payoff
+            .clamp(-strategy.owner_collateral, strategy.pool_collateral);
+        let owner_payout = add(env, strategy.owner_collateral, payoff);
 
+        strategy.is_active = false;
         env.storage()
             .persistent()
-            .set(&DataKey::Option(option_id), &option);
-        env.storage()
-            .instance()
-            .set(&DataKey::OptionCounter, &(option_id + 1));
+            .set(&DataKey::Strategy(strategy.strategy_id), &strategy);
+
+        let pool_id = strategy.pool_id;
+        let mut open_strategies = Self::get_pool_open_strategies(env.clone(), pool_id);
+        if let Some(index) = open_strategies.first_index_of(strategy.strategy_id) {
+            open_strategies.remove(index);
+            env.storage()
+                .persistent()
+                .set(&DataKey::PoolOpenStrategies(pool_id), &open_strategies);
+        }
+        let liquidity = Self::get_pool_total_liquidity(env.clone(), pool_id);
+        env.storage().persistent().set(
+            &DataKey::PoolTotalLiquidity(pool_id),
+            &sub(env, liquidity, payoff),
+        );
+        let locked_collateral = Self::get_pool_locked_collateral(env.clone(), pool_id);
+        env.storage().persistent().set(
+            &DataKey::PoolLockedCollateral(pool_id),
+            &sub(env, locked_collateral, strategy.pool_collateral),
+        );
+
+        if owner_payout > 0 {
+            let token_client = TokenClient::new(env, &pool.stable_token);
+            token_client.transfer(
+                &env.current_contract_address(),
+                &strategy.owner,
+                &owner_payout,
+            );
+        }
 
-        // Emit event
         env.events().publish(
-            (OPTION_PURCHASED, buyer),
-            (
-                option_id,
-                pool_id,
-                opt_type,
-                strike,
-                expiry,
-                amount,
-                premium,
-                collateral_needed,
-            ),
+            (STRATEGY_SETTLED, strategy.owner),
+            (strategy.strategy_id, payoff),
         );
 
-        option_id
+        owner_payout
     }
 
-    /// Exercise an option (American-style)
-    pub fn exercise_option(env: Env, option_id: u64) -> i128 {
-        let mut option = Self::get_option(env.clone(), option_id);
+    // (pool max loss, owner max loss) of a strategy's combined payoff. Legs with
+    // different expiries settle at different prices, so each expiry's losses are found
+    // on its own and summed.
+    fn strategy_max_losses(env: &Env, pool: &PoolData, legs: &Vec<StrategyLeg>) -> (i128, i128) {
+        let mut expiries: Vec<u64> = Vec::new(env);
+        for leg in legs.iter() {
+            if !expiries.contains(leg.expiry) {
+                expiries.push_back(leg.expiry);
+            }
+        }
 
-        if !option.is_active {
-            panic_with_error!(&env, OptionsError::OptionNotActive);
+        let mut pool_loss = 0i128;
+        let mut owner_loss = 0i128;
+        for expiry in expiries.iter() {
+            let mut group = Vec::new(env);
+            for leg in legs.iter() {
+                if leg.expiry == expiry {
+                    group.push_back(leg);
+                }
+            }
+            let (group_pool_loss, group_owner_loss) = Self::expiry_max_losses(env, pool, &group);
+            pool_loss = add(env, pool_loss, group_pool_loss);
+            owner_loss = add(env, owner_loss, group_owner_loss);
         }
+        (pool_loss, owner_loss)
+    }
 
-        option.buyer.require_auth();
+    // (pool max loss, owner max loss) of legs settling at one price. The payoff is
+    // piecewise linear with kinks at the strikes, so its extremes over [0, 2 * highest
+    // strike] are at those points; calls are covered up to twice their strike, as with
+    // the strike-sized collateral of single-leg calls.
+    fn expiry_max_losses(env: &Env, pool: &PoolData, legs: &Vec<StrategyLeg>) -> (i128, i128) {
+        let mut prices = Vec::new(env);
+        prices.push_back(0i128);
+        let mut highest = 0i128;
+        for leg in legs.iter() {
+            prices.push_back(leg.strike);
+            highest = highest.max(leg.strike);
+        }
+        prices.push_back(mul(env, highest, 2));
 
-        if env.ledger().timestamp() > option.expiry {
-            panic_with_error!(&env, OptionsError::OptionExpired);
+        let mut pool_loss = 0i128;
+        let mut owner_loss = 0i128;
+        for price in prices.iter() {
+            pool_loss = pool_loss.max(Self::strategy_payoff(env, pool, legs, price, true));
+            owner_loss = owner_loss.max(-Self::strategy_payoff(env, pool, legs, price, false));
         }
+        (pool_loss, owner_loss)
+    }
 
-        let pool = Self::get_pool(env.clone(), option.pool_id);
+    // Oracle prints between `from` and `to`, oldest first, looked up by timestamp one
+    // resolution period at a time. Reads at most MAX_PRICE_RECORDS periods from `from`;
+    // returns the prints and the last timestamp covered.
+    fn price_history(env: &Env, pool: &PoolData, from: u64, to: u64) -> (Vec<PriceData>, u64) {
+        let price_feed_client = PriceFeedClient::new(env, &pool.price_feed);
+        let asset = Asset::Other(Symbol::new(env, "XLM")); // same asset get_price_from_feed reads
 
-        // Get current price from the pool's price feed
-        let current_price = Self::get_price_from_feed(env.clone(), pool.price_feed);
-        let mut payoff = 0i128;
+        let resolution = price_feed_client.resolution().max(1) as u64;
+        let first_period = from - from % resolution;
+        let to = to.min(first_period + MAX_PRICE_RECORDS as u64 * resolution - 1);
 
-        // Calculate payoff
-        match option.opt_type {
-            OptionType::Call => {
-                if current_price > option.strike {
-                    let normalized_amount = option.amount / 10_000_000;
-                    payoff = (current_price - option.strike)
-                        * if normalized_amount == 0 {
-                            1
-                        } else {
-                            normalized_amount
-                        };
+        let mut history = Vec::new(env);
+        let mut period = first_period;
+        while period <= to {
+            if let Some(record) = price_feed_client.price(&asset, &period) {
+                if record.timestamp >= from && record.timestamp <= to {
+                    history.push_back(record);
                 }
             }
-            OptionType::Put => {
-                if current_price < option.strike {
-                    let normalized_amount = option.amount / 10_000_000;
-                    payoff = (option.strike - current_price)
-                        * if normalized_amount == 0 {
-                            1
-                        } else {
-                            normalized_amount
-                        };
+            period += resolution;
+        }
+        (history, to)
+    }
+
+    // Last oracle print at or before expiry, looked up by timestamp one resolution period
+    // at a time so it can be read for as long as the feed keeps its history
+    fn expiry_price(env: &Env, pool: &PoolData, expiry: u64) -> i128 {
+        let price_feed_client = PriceFeedClient::new(env, &pool.price_feed);
+        let asset = Asset::Other(Symbol::new(env, "XLM")); // same asset get_price_from_feed reads
+
+        let resolution = price_feed_client.resolution().max(1) as u64;
+        let oldest = expiry.saturating_sub(MAX_EXPIRY_PRICE_AGE);
+        let mut period = expiry - expiry % resolution;
+        loop {
+            if let Some(record) = price_feed_client.price(&asset, &period) {
+                if record.timestamp <= expiry && record.timestamp >= oldest {
+                    return record.price;
                 }
             }
+            if period < oldest + resolution {
+                panic_with_error!(env, OptionsError::InvalidPrice);
+            }
+            period -= resolution;
         }
+    }
 
-        // Update option status
-        option.is_active = false;
-        option.is_exercised = true;
-        env.storage()
-            .persistent()
-            .set(&DataKey::Option(option_id), &option);
-
-        // Update locked collateral for this pool
-        let locked_collateral = Self::get_pool_locked_collateral(env.clone(), option.pool_id);
-        env.storage().persistent().set(
-            &DataKey::PoolLockedCollateral(option.pool_id),
-            &(locked_collateral - option.collateral),
-        );
+    // Arithmetic mean of the oracle prints in an Asian option's averaging window
+    fn average_price(env: &Env, pool: &PoolData, expiry: u64, averaging: &AveragingParams) -> i128 {
+        let (history, _) = Self::price_history(env, pool, expiry - averaging.window, expiry);
+        if history.len() < averaging.min_samples {
+            panic_with_error!(env, OptionsError::InvalidPrice);
+        }
 
-        // Transfer payoff if any
-        if payoff > 0 {
-            let actual_payoff = if payoff > option.collateral {
-                option.collateral
-            } else {
-                payoff
-            };
-            let token_client = TokenClient::new(&env, &pool.stable_token);
-            token_client.transfer(
-                &env.current_contract_address(),
-                &option.buyer,
-                &actual_payoff,
-            );
+        let mut total = 0i128;
+        for record in history.iter() {
+            total = total
+                .checked_add(record.price)
+                .unwrap_or_else(|| panic_with_error!(env, OptionsError::ArithmeticOverflow));
+        }
+        total / history.len() as i128
+    }
 
-            env.events()
-                .publish((OPTION_EXERCISED, option.buyer), (option_id, actual_payoff));
-            actual_payoff
+    // Cash-or-nothing options pay their whole collateral when in the money at expiry
+    fn digital_payoff(env: &Env, pool: &PoolData, option: &OptionData) -> i128 {
+        let settlement_price = Self::expiry_price(env, pool, option.expiry);
+        let in_the_money = match option.opt_type {
+            OptionType::Call => settlement_price > option.strike,
+            OptionType::Put => settlement_price < option.strike,
+        };
+        if in_the_money {
+            option.collateral
         } else {
-            env.events()
-                .publish((OPTION_EXERCISED, option.buyer), (option_id, 0i128));
             0
         }
     }
 
-    /// Expire an option (release collateral)
-    pub fn expire_option(env: Env, option_id: u64) {
-        let mut option = Self::get_option(env.clone(), option_id);
+    // Earliest oracle print at or through the barrier in the next page of history after
+    // `monitor_from`, and the last timestamp that page covered
+    fn find_barrier_crossing(
+        env: &Env,
+        pool: &PoolData,
+        option: &OptionData,
+        barrier: &Barrier,
+    ) -> (Option<PriceData>, u64) {
+        let until = env.ledger().timestamp().min(option.expiry);
+        let (history, scanned_to) = Self::price_history(env, pool, barrier.monitor_from, until);
+        let crossing = history.iter().find(|record| match barrier.direction {
+            BarrierDirection::Up => record.price >= barrier.level,
+            BarrierDirection::Down => record.price <= barrier.level,
+        });
+        (crossing, scanned_to)
+    }
 
-        if !option.is_active {
-            panic_with_error!(&env, OptionsError::OptionNotActive);
+    fn knock_out(env: &Env, option_id: u64, mut option: OptionData, crossing: PriceData) {
+        option.is_active = false;
+        if let OptionBarrier::Barrier(barrier) = &mut option.barrier {
+            barrier.is_crossed = true;
         }
+        env.storage()
+            .persistent()
+            .set(&DataKey::Option(option_id), &option);
 
-        if env.ledger().timestamp() <= option.expiry {
-            panic_with_error!(&env, OptionsError::OptionExpired);
+        Self::release_collateral(env, option_id, &option);
+        if let Some(series_id) = option.series_id {
+            Self::update_open_interest(env, series_id, -option.amount);
         }
 
-        // Mark as expired
-        option.is_active = false;
-        option.is_exercised = false;
-        env.storage()
+        env.events().publish(
+            (BARRIER_CROSSED, option.buyer),
+            (
+                option_id,
+                BarrierKind::KnockOut,
+                crossing.price,
+                crossing.timestamp,
+            ),
+        );
+    }
+
+    fn model_volatility(env: &Env, pool_id: u64, spot: i128, strike: i128, tenor: u64) -> i128 {
+        let surface: Option<VolSurface> = env
+            .storage()
             .persistent()
-            .set(&DataKey::Option(option_id), &option);
+            .get(&DataKey::PoolVolSurface(pool_id));
+        match surface {
+            Some(surface) => pricing::interpolate_vol(
+                env,
+                &surface.moneyness,
+                &surface.tenors,
+                &surface.vols,
+                mul_div_floor(env, strike, SCALE, spot),
+                tenor,
+            ),
+            None => Self::get_pool_volatility(env.clone(), pool_id),
+        }
+    }
 
-        // Free collateral from pool
-        let locked_collateral = Self::get_pool_locked_collateral(env.clone(), option.pool_id);
-        env.storage().persistent().set(
-            &DataKey::PoolLockedCollateral(option.pool_id),
-            &(locked_collateral - option.collateral),
+    // Model value of one unit of an option; perpetuals are valued as the FUNDING_PERIOD
+    // option their funding rents
+    fn model_unit_price(env: &Env, option: &OptionData, spot: i128, vol: i128, tenor: u64) -> i128 {
+        let years = pricing::years_until(env, 0, tenor);
+        let is_call = option.opt_type == OptionType::Call;
+        match &option.payoff {
+            OptionPayoff::CashOrNothing(payout) => mul_div_floor(
+                env,
+                *payout,
+                pricing::digital_price(env, spot, option.strike, vol, years, is_call),
+                SCALE,
+            ),
+            OptionPayoff::Average(averaging) => pricing::asian_price(
+                env,
+                spot,
+                option.strike,
+                vol,
+                years,
+                pricing::years_until(env, 0, averaging.window),
+                is_call,
+            ),
+            OptionPayoff::Vanilla => match option.barrier.get() {
+                Some(barrier) if !barrier.is_crossed => pricing::barrier_price(
+                    env,
+                    spot,
+                    option.strike,
+                    barrier.level,
+                    vol,
+                    years,
+                    is_call,
+                    barrier.direction == BarrierDirection::Up,
+                    barrier.kind == BarrierKind::KnockIn,
+                ),
+                _ => {
+                    let variance =
+                        mul_div_floor(env, mul_div_floor(env, vol, vol, SCALE), years, SCALE);
+                    pricing::black_price(env, spot, option.strike, variance, is_call)
+                }
+            },
+        }
+    }
+
+    // Greeks by bump-and-reprice of the model value, so every payoff shares one method
+    fn option_greeks(env: &Env, pool: &PoolData, option: &OptionData, spot: i128) -> OptionGreeks {
+        let tenor = Self::option_tenor(env, option);
+        let vol = Self::model_volatility(env, option.pool_id, spot, option.strike, tenor);
+        let price = |spot: i128, vol: i128, tenor: u64| {
+            Self::model_unit_price(env, option, spot, vol, tenor)
+        };
+
+        let bump = mul_div_floor(env, spot, GREEK_SPOT_BUMP_BPS, BPS).max(1);
+        let base = price(spot, vol, tenor);
+        let up = price(add(env, spot, bump), vol, tenor);
+        let down = price(sub(env, spot, bump), vol, tenor);
+
+        let unit_delta = mul_div_floor(env, sub(env, up, down), SCALE, mul(env, 2, bump));
+        let convexity = sub(env, add(env, up, down), mul(env, 2, base));
+        let unit_gamma =
+            mul_div_floor(env, mul_div_floor(env, convexity, SCALE, bump), SCALE, bump);
+        let unit_vega = sub(env, price(spot, add(env, vol, GREEK_VOL_BUMP), tenor), base);
+        let unit_theta = sub(
+            env,
+            price(spot, vol, tenor.saturating_sub(GREEK_TIME_STEP)),
+            base,
         );
 
-        env.events()
-            .publish((OPTION_EXPIRED, option.buyer), option_id);
+        let position_value =
+            |unit: i128| value_floor(env, unit, option.amount, pool.stable_decimals);
+        OptionGreeks {
+            price: position_value(base),
+            delta: mul_div_floor(env, unit_delta, option.amount, SCALE),
+            gamma: mul_div_floor(env, unit_gamma, option.amount, SCALE),
+            vega: position_value(unit_vega),
+            theta: position_value(unit_theta),
+        }
     }
 
-    // View functions for pools
-    pub fn get_pool_counter(env: Env) -> u64 {
-        env.storage()
-            .instance()
-            .get(&DataKey::PoolCounter)
-            .unwrap_or(0)
+    // Seconds of optionality left that the model prices
+    fn option_tenor(env: &Env, option: &OptionData) -> u64 {
+        if option.expiry == PERPETUAL_EXPIRY {
+            FUNDING_PERIOD
+        } else {
+            option.expiry.saturating_sub(env.ledger().timestamp())
+        }
     }
 
-    pub fn get_pool(env: Env, pool_id: u64) -> PoolData {
-        env.storage()
-            .persistent()
-            .get(&DataKey::Pool(pool_id))
-            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::PoolNotFound))
+    // What the pool owes an option holder at `spot`: model value, but never less than
+    // immediate exercise would pay, and never more than the collateral
+    fn option_liability(env: &Env, pool: &PoolData, option: &OptionData, spot: i128) -> i128 {
+        // Physical calls are delivered from the underlying inventory, not stable liquidity
+        if option.settlement == SettlementType::Physical && option.opt_type == OptionType::Call {
+            return 0;
+        }
+        let tenor = Self::option_tenor(env, option);
+        let vol = Self::model_volatility(env, option.pool_id, spot, option.strike, tenor);
+        let model = value_ceil(
+            env,
+            Self::model_unit_price(env, option, spot, vol, tenor),
+            option.amount,
+            pool.stable_decimals,
+        );
+        model
+            .max(Self::exercise_value(env, pool, option, spot))
+            .min(option.collateral)
     }
 
-    pub fn get_pool_by_assets(env: Env, stable_token: Address, underlying_asset: Address) -> u64 {
-        env.storage()
-            .persistent()
-            .get(&DataKey::PoolExists(stable_token, underlying_asset))
-            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::PoolNotFound))
+    fn mark_to_market(env: &Env, pool: &PoolData) -> PoolMarkToMarket {
+        let open_options = Self::get_pool_open_options(env.clone(), pool.pool_id);
+        let open_strategies = Self::get_pool_open_strategies(env.clone(), pool.pool_id);
+        Self::mark_positions(env, pool, open_options, open_strategies)
     }
 
-    pub fn get_pool_total_liquidity(env: Env, pool_id: u64) -> i128 {
-        env.storage()
-            .persistent()
-            .get(&DataKey::PoolTotalLiquidity(pool_id))
-            .unwrap_or(0)
+    // Mark `open_options` and `open_strategies` against the pool's whole liquidity
+    fn mark_positions(
+        env: &Env,
+        pool: &PoolData,
+        open_options: Vec<u64>,
+        open_strategies: Vec<u64>,
+    ) -> PoolMarkToMarket {
+        let total_liquidity = Self::get_pool_total_liquidity(env.clone(), pool.pool_id);
+        let delivered = Self::get_pool_delivered_underlying(env.clone(), pool.pool_id);
+
+        let mut delivered_value = 0i128;
+        let mut option_liability = 0i128;
+        let mut open_premiums = 0i128;
+        if delivered > 0 || !open_options.is_empty() || !open_strategies.is_empty() {
+            let spot = Self::get_price_from_feed(env.clone(), pool.price_feed.clone());
+            delivered_value = convert_floor(
+                env,
+                delivered,
+                spot,
+                SCALE,
+                pool.underlying_decimals,
+                pool.stable_decimals,
+            );
+            for option_id in open_options.iter() {
+                let option = Self::get_option(env.clone(), option_id);
+                option_liability = add(
+                    env,
+                    option_liability,
+                    Self::option_liability(env, pool, &option, spot),
+                );
+                open_premiums = add(env, open_premiums, option.premium_paid);
+            }
+            for strategy_id in open_strategies.iter() {
+                let strategy = Self::get_strategy(env.clone(), strategy_id);
+                option_liability = add(
+                    env,
+                    option_liability,
+                    Self::strategy_liability(env, pool, &strategy, spot),
+                );
+                open_premiums = add(env, open_premiums, strategy.net_premium);
+            }
+        }
+
+        PoolMarkToMarket {
+            total_liquidity,
+            delivered_value,
+            option_liability,
+            nav: sub(
+                env,
+                add(env, total_liquidity, delivered_value),
+                option_liability,
+            ),
+            open_premiums,
+            unrealized_pnl: open_premiums - option_liability,
+            open_options: open_options.len(),
+            open_strategies: open_strategies.len(),
+        }
     }
 
-    pub fn get_pool_locked_collateral(env: Env, pool_id: u64) -> i128 {
-        env.storage()
-            .persistent()
-            .get(&DataKey::PoolLockedCollateral(pool_id))
-            .unwrap_or(0)
+    // Legs of a strategy that have not settled yet
+    fn open_strategy_legs(env: &Env, strategy: &StrategyData) -> Vec<StrategyLeg> {
+        let mut legs = Vec::new(env);
+        for index in 0..strategy.legs.len() {
+            let key = DataKey::StrategyLeg(strategy.strategy_id, index);
+            let record: StrategyLegRecord = env.storage().persistent().get(&key).unwrap();
+            if !record.is_settled {
+                legs.push_back(record.leg);
+            }
+        }
+        legs
     }
 
-    pub fn get_pool_total_lp_shares(env: Env, pool_id: u64) -> i128 {
-        env.storage()
-            .persistent()
-            .get(&DataKey::PoolTotalLpShares(pool_id))
-            .unwrap_or(0)
+    // A strategy leg as the European option the owner holds or wrote. Payoffs are capped
+    // for the strategy as a whole, so the leg carries no collateral cap of its own.
+    fn leg_option(strategy: &StrategyData, leg: &StrategyLeg) -> OptionData {
+        OptionData {
+            pool_id: strategy.pool_id,
+            buyer: strategy.owner.clone(),
+            opt_type: leg.opt_type.clone(),
+            strike: leg.strike,
+            expiry: leg.expiry,
+            amount: leg.amount,
+            premium_paid: 0,
+            collateral: i128::MAX,
+            is_exercised: false,
+            is_active: true,
+            style: OptionStyle::European,
+            series_id: None,
+            settlement: SettlementType::Cash,
+            writer: OptionWriter::Pool,
+            barrier: OptionBarrier::NoBarrier,
+            payoff: OptionPayoff::Vanilla,
+        }
     }
 
-    pub fn get_pool_lp_shares(env: Env, pool_id: u64, provider: Address) -> i128 {
-        env.storage()
-            .persistent()
-            .get(&DataKey::PoolLpShares(pool_id, provider))
-            .unwrap_or(0)
+    // What the pool owes a strategy's owner at `spot`, negative when the owner owes the
+    // pool: settled legs at their payoff and open legs at their option value, capped by
+    // what each side locked
+    fn strategy_liability(env: &Env, pool: &PoolData, strategy: &StrategyData, spot: i128) -> i128 {
+        let mut liability = strategy.settled_payoff;
+        for leg in Self::open_strategy_legs(env, strategy).iter() {
+            let value = Self::option_liability(env, pool, &Self::leg_option(strategy, &leg), spot);
+            liability = match leg.side {
+                PositionSide::Long => add(env, liability, value),
+                PositionSide::Short => sub(env, liability, value),
+            };
+        }
+        liability.clamp(-strategy.owner_collateral, strategy.pool_collateral)
     }
 
-    // Other view functions
-    pub fn get_option_counter(env: Env) -> u64 {
-        env.storage()
-            .instance()
-            .get(&DataKey::OptionCounter)
-            .unwrap_or(0)
+    // What the pool would pay a strategy's owner if its open legs settled at `price`
+    fn strategy_payout(
+        env: &Env,
+        pool: &PoolData,
+        strategy: &StrategyData,
+        open_legs: &Vec<StrategyLeg>,
+        price: i128,
+    ) -> i128 {
+        add(
+            env,
+            strategy.settled_payoff,
+            Self::strategy_payoff(env, pool, open_legs, price, true),
+        )
+        .clamp(-strategy.owner_collateral, strategy.pool_collateral)
     }
 
-    pub fn get_option(env: Env, option_id: u64) -> OptionData {
-        env.storage()
-            .persistent()
-            .get(&DataKey::Option(option_id))
-            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::OptionNotFound))
+    fn pool_nav(env: &Env, pool: &PoolData) -> i128 {
+        Self::mark_to_market(env, pool).nav
     }
 
-    pub fn get_admin(env: Env) -> Address {
-        env.storage()
-            .instance()
-            .get(&DataKey::Admin)
-            .unwrap_or_else(|| panic_with_error!(&env, OptionsError::NotInitialized))
+    // NAV less the underlying delivered by physical puts, which LPs redeem in kind; the
+    // part of NAV paid out in stable
+    fn stable_nav(mtm: &PoolMarkToMarket) -> i128 {
+        mtm.nav - mtm.delivered_value
     }
 
-    // Get price from SEP-40 oracle
-    pub fn get_price_from_feed(env: Env, price_feed: Address) -> i128 {
-        // Create a client for the SEP-40 price feed oracle
-        let price_feed_client = PriceFeedClient::new(&env, &price_feed);
+    // Payout of exercising an option at `price`, capped by stable collateral
+    fn exercise_value(env: &Env, pool: &PoolData, option: &OptionData, price: i128) -> i128 {
+        let in_the_money = match option.opt_type {
+            OptionType::Call => (price - option.strike).max(0),
+            OptionType::Put => (option.strike - price).max(0),
+        };
+        let value = match option.payoff {
+            OptionPayoff::CashOrNothing(payout) if in_the_money > 0 => {
+                value_floor(env, payout, option.amount, pool.stable_decimals)
+            }
+            OptionPayoff::CashOrNothing(_) => 0,
+            _ => value_floor(env, in_the_money, option.amount, pool.stable_decimals),
+        };
+        // Physical calls lock underlying rather than stable
+        if option.settlement == SettlementType::Physical && option.opt_type == OptionType::Call {
+            value
+        } else {
+            value.min(option.collateral)
+        }
+    }
 
-        // Create an Asset instance for the asset we want to get the price for
-        // In this case, we're using the default asset (typically the base asset of the oracle)
-        let asset = Asset::Other(Symbol::new(&env, "XLM"));
+    // Model funding for `elapsed` seconds: the time value of a FUNDING_PERIOD option at spot,
+    // paid once per period
+    fn funding_due(env: &Env, pool: &PoolData, option: &OptionData, elapsed: u64) -> i128 {
+        let spot = Self::get_price_from_feed(env.clone(), pool.price_feed.clone());
+        let volatility =
+            Self::model_volatility(env, option.pool_id, spot, option.strike, FUNDING_PERIOD);
+        let variance = mul_div_ceil(
+            env,
+            mul_div_ceil(env, volatility, volatility, SCALE),
+            FUNDING_PERIOD as i128,
+            pricing::SECONDS_PER_YEAR,
+        );
 
-        // Get the latest price from the oracle
-        let price_data_opt = price_feed_client.lastprice(&asset);
+        let is_call = option.opt_type == OptionType::Call;
+        let intrinsic = if is_call {
+            (spot - option.strike).max(0)
+        } else {
+            (option.strike - spot).max(0)
+        };
+        let time_value =
+            (pricing::black_price(env, spot, option.strike, variance, is_call) - intrinsic).max(0);
 
-        // Handle the Option<PriceData> return type
-        match price_data_opt {
-            Some(price_data) => {
-                // Extract the price from the PriceData
-                price_data.price
-            }
-            None => {
-                // If no price is available, panic with an error
-                panic_with_error!(&env, OptionsError::InvalidPrice);
-            }
+        let per_period = value_ceil(env, time_value, option.amount, pool.stable_decimals);
+        mul_div_ceil(env, per_period, elapsed as i128, FUNDING_PERIOD as i128)
+    }
+
+    // Charge funding accrued since the last touch from the deposit; any shortfall is
+    // carried as unpaid and makes the position liquidatable
+    fn accrue_funding(
+        env: &Env,
+        option_id: u64,
+        option: &OptionData,
+        pool: &PoolData,
+    ) -> PerpetualFunding {
+        if !option.is_active {
+            panic_with_error!(env, OptionsError::OptionNotActive);
+        }
+        let mut funding = Self::get_perpetual_funding(env.clone(), option_id);
+
+        let now = env.ledger().timestamp();
+        if now > funding.accrued_at {
+            let due = Self::funding_due(env, pool, option, now - funding.accrued_at);
+            let paid = due.min(funding.deposit);
+            funding.deposit = sub(env, funding.deposit, paid);
+            funding.unpaid = add(env, funding.unpaid, due - paid);
+            funding.total_paid = add(env, funding.total_paid, paid);
+            funding.accrued_at = now;
+            env.storage()
+                .persistent()
+                .set(&DataKey::PerpetualFunding(option_id), &funding);
+            Self::adjust_pool_liquidity(env, option.pool_id, paid);
         }
+
+        funding
     }
 
-    // Admin function to update price feed for a pool
-    pub fn update_pool_price_feed(env: Env, pool_id: u64, new_feed: Address) {
-        let admin = Self::get_admin(env.clone());
-        admin.require_auth();
+    // Settle a perpetual's funding on exercise: refund the deposit left and return the
+    // payoff net of unpaid funding
+    fn close_funding(
+        env: &Env,
+        option_id: u64,
+        option: &OptionData,
+        pool: &PoolData,
+        payoff: i128,
+    ) -> i128 {
+        let mut funding = Self::accrue_funding(env, option_id, option, pool);
+        let payoff = payoff.min(option.collateral);
 
-        let mut pool = Self::get_pool(env.clone(), pool_id);
-        pool.price_feed = new_feed;
+        if funding.deposit > 0 {
+            let token_client = TokenClient::new(env, &pool.stable_token);
+            token_client.transfer(
+                &env.current_contract_address(),
+                &option.buyer,
+                &funding.deposit,
+            );
+        }
+
+        // The pool keeps what it would have paid out; funding beyond the payoff is written off
+        let withheld = funding.unpaid.min(payoff);
+        funding.total_paid = add(env, funding.total_paid, withheld);
+        funding.deposit = 0;
+        funding.unpaid = 0;
         env.storage()
             .persistent()
-            .set(&DataKey::Pool(pool_id), &pool);
+            .set(&DataKey::PerpetualFunding(option_id), &funding);
+
+        payoff - withheld
     }
 
-    // Get all pools (for UI purposes) - returns Vec of pool IDs
-    pub fn get_all_pools(env: Env) -> Vec<u64> {
-        let pool_count = Self::get_pool_counter(env.clone());
-        let mut pools = Vec::new(&env);
+    // Book premiums and other income (positive) or payouts (negative) against a pool
+    fn adjust_pool_liquidity(env: &Env, pool_id: u64, delta: i128) {
+        if delta != 0 {
+            let liquidity = Self::get_pool_total_liquidity(env.clone(), pool_id);
+            env.storage().persistent().set(
+                &DataKey::PoolTotalLiquidity(pool_id),
+                &add(env, liquidity, delta),
+            );
+        }
+    }
 
-        for i in 0..pool_count {
-            if env.storage().persistent().has(&DataKey::Pool(i)) {
-                pools.push_back(i);
+    // Return whatever an account writer's escrow didn't pay out to the holder
+    fn refund_writer(env: &Env, option: &OptionData, pool: &PoolData, paid_out: i128) {
+        if let OptionWriter::Account(writer) = &option.writer {
+            let refund = option.collateral - paid_out;
+            if refund > 0 {
+                let token_client = TokenClient::new(env, &pool.stable_token);
+                token_client.transfer(&env.current_contract_address(), writer, &refund);
             }
         }
+    }
 
-        pools
+    fn update_open_interest(env: &Env, series_id: u64, delta: i128) {
+        let mut series = Self::get_series(env.clone(), series_id);
+        series.open_interest = add(env, series.open_interest, delta);
+        env.storage()
+            .persistent()
+            .set(&DataKey::Series(series_id), &series);
     }
 }
 
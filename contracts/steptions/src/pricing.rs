@@ -0,0 +1,271 @@
+// Black-Scholes pricing in 1e7 fixed point.
+//
+// Volatility and time are annualized; the risk-free rate is taken as zero since premiums
+// and collateral are both held in the pool's stable token.
+use crate::fixed_point::{exp, ln, mul_div_floor, sqrt, SCALE};
+use soroban_sdk::{Env, Vec};
+
+pub const SECONDS_PER_YEAR: i128 = 31_536_000;
+
+// 1 / sqrt(2 * pi)
+const INV_SQRT_2PI: i128 = 3_989_423;
+
+// Abramowitz & Stegun 26.2.17 coefficients (absolute error below 7.5e-8)
+const CDF_P: i128 = 2_316_419;
+const CDF_B: [i128; 5] = [3_193_815, -3_565_638, 17_814_779, -18_212_560, 13_302_744];
+
+// Time to expiry in years
+pub fn years_until(env: &Env, now: u64, expiry: u64) -> i128 {
+    mul_div_floor(
+        env,
+        expiry.saturating_sub(now) as i128,
+        SCALE,
+        SECONDS_PER_YEAR,
+    )
+}
+
+// Standard normal density
+pub fn norm_pdf(env: &Env, x: i128) -> i128 {
+    let half_square = mul_div_floor(env, x, x, 2 * SCALE);
+    mul_div_floor(env, exp(env, -half_square), INV_SQRT_2PI, SCALE)
+}
+
+// Standard normal cumulative distribution
+pub fn norm_cdf(env: &Env, x: i128) -> i128 {
+    if x > 8 * SCALE {
+        return SCALE;
+    }
+    if x < -8 * SCALE {
+        return 0;
+    }
+
+    let t = mul_div_floor(
+        env,
+        SCALE,
+        SCALE,
+        SCALE + mul_div_floor(env, CDF_P, x.abs(), SCALE),
+    );
+    let mut polynomial = 0i128;
+    for b in CDF_B.iter().rev() {
+        polynomial = mul_div_floor(env, polynomial + b, t, SCALE);
+    }
+    let tail = mul_div_floor(env, norm_pdf(env, x), polynomial, SCALE);
+
+    if x >= 0 {
+        SCALE - tail
+    } else {
+        tail
+    }
+}
+
+// d2 = (ln(S / K) - vol^2 * T / 2) / (vol * sqrt(T)), or None once no time or
+// volatility is left and the outcome is decided by moneyness alone
+pub fn d2(env: &Env, spot: i128, strike: i128, vol: i128, years: i128) -> Option<i128> {
+    let vol_sqrt_t = mul_div_floor(env, vol, sqrt(env, years), SCALE);
+    if vol_sqrt_t == 0 {
+        return None;
+    }
+    let log_moneyness = ln(env, mul_div_floor(env, spot, SCALE, strike));
+    let half_variance = mul_div_floor(env, mul_div_floor(env, vol, vol, SCALE), years, 2 * SCALE);
+    Some(mul_div_floor(
+        env,
+        log_moneyness - half_variance,
+        SCALE,
+        vol_sqrt_t,
+    ))
+}
+
+// Probability-weighted value of a cash-or-nothing option paying 1 (scaled 1e7)
+pub fn digital_price(
+    env: &Env,
+    spot: i128,
+    strike: i128,
+    vol: i128,
+    years: i128,
+    is_call: bool,
+) -> i128 {
+    match d2(env, spot, strike, vol, years) {
+        Some(d2) if is_call => norm_cdf(env, d2),
+        Some(d2) => norm_cdf(env, -d2),
+        None if (spot > strike) == is_call && spot != strike => SCALE,
+        None => 0,
+    }
+}
+
+// Black-76 value per unit of a vanilla option on `forward` with `total_variance`
+// (vol^2 * T), or the intrinsic value once no variance is left
+pub fn black_price(
+    env: &Env,
+    forward: i128,
+    strike: i128,
+    total_variance: i128,
+    is_call: bool,
+) -> i128 {
+    let intrinsic = if is_call {
+        (forward - strike).max(0)
+    } else {
+        (strike - forward).max(0)
+    };
+    let std_dev = sqrt(env, total_variance);
+    if std_dev == 0 {
+        return intrinsic;
+    }
+
+    let log_moneyness = ln(env, mul_div_floor(env, forward, SCALE, strike));
+    let d1 = mul_div_floor(env, log_moneyness + total_variance / 2, SCALE, std_dev);
+    let d2 = d1 - std_dev;
+    let price = if is_call {
+        mul_div_floor(env, forward, norm_cdf(env, d1), SCALE)
+            - mul_div_floor(env, strike, norm_cdf(env, d2), SCALE)
+    } else {
+        mul_div_floor(env, strike, norm_cdf(env, -d2), SCALE)
+            - mul_div_floor(env, forward, norm_cdf(env, -d1), SCALE)
+    };
+    // Rounding can leave deep out-of-the-money values a hair below zero
+    price.max(0)
+}
+
+// Continuously monitored barrier option by the method of images. With zero rates, if
+// V(S) prices a payoff then so does (S / H) * V(H^2 / S), so the vanilla payoff cut off
+// at the barrier H, less its image, is the knock-out value: both agree away from the
+// barrier and cancel on it. Knock-ins are worth the vanilla less the knock-out. Early
+// exercise is worth nothing at zero rates, so this prices the American options too.
+pub fn barrier_price(
+    env: &Env,
+    spot: i128,
+    strike: i128,
+    level: i128,
+    vol: i128,
+    years: i128,
+    is_call: bool,
+    is_up: bool,
+    knock_in: bool,
+) -> i128 {
+    let total_variance = mul_div_floor(env, mul_div_floor(env, vol, vol, SCALE), years, SCALE);
+    let vanilla = black_price(env, spot, strike, total_variance, is_call);
+
+    // Already through the barrier: knocked in, or knocked out
+    let crossed = if is_up { spot >= level } else { spot <= level };
+    let knock_out = if crossed {
+        0
+    } else {
+        let image_spot = mul_div_floor(env, level, level, spot);
+        let truncated = |spot: i128| {
+            let call = |strike: i128| black_price(env, spot, strike, total_variance, true);
+            let put = |strike: i128| black_price(env, spot, strike, total_variance, false);
+            // (distance to the barrier) x P(finishing beyond it)
+            let step = |width: i128, above: bool| {
+                let probability = digital_price(env, spot, level, vol, years, above);
+                mul_div_floor(env, width, probability, SCALE)
+            };
+            match (is_up, is_call) {
+                (true, true) if strike >= level => 0,
+                (true, true) => call(strike) - call(level) - step(level - strike, true),
+                (true, false) if strike <= level => put(strike),
+                (true, false) => put(level) + step(strike - level, false),
+                (false, true) if strike >= level => call(strike),
+                (false, true) => call(level) + step(level - strike, true),
+                (false, false) if strike <= level => 0,
+                (false, false) => put(strike) - put(level) - step(strike - level, false),
+            }
+        };
+        let image = mul_div_floor(env, truncated(image_spot), spot, level);
+        (truncated(spot) - image).clamp(0, vanilla)
+    };
+
+    if knock_in {
+        vanilla - knock_out
+    } else {
+        knock_out
+    }
+}
+
+// Arithmetic-average option by moment matching (Turnbull-Wakeman): the average over
+// the last `window_years` before expiry is treated as lognormal with the same first
+// two moments. With zero rates its mean is spot and
+//   E[A^2] / spot^2 = e^(vol^2 * t1) * 2 * (e^b - 1 - b) / b^2,  b = vol^2 * window
+// where t1 is the time until averaging starts.
+pub fn asian_price(
+    env: &Env,
+    spot: i128,
+    strike: i128,
+    vol: i128,
+    years: i128,
+    window_years: i128,
+    is_call: bool,
+) -> i128 {
+    let window_years = window_years.min(years);
+    let variance_rate = mul_div_floor(env, vol, vol, SCALE);
+    let pre_window = mul_div_floor(env, variance_rate, years - window_years, SCALE);
+    let b = mul_div_floor(env, variance_rate, window_years, SCALE);
+
+    // 2 * (e^b - 1 - b) / b^2 = sum of 2 * b^n / (n + 2)!, summed directly since the
+    // closed form cancels catastrophically for short windows
+    let mut term = SCALE;
+    let mut moment_ratio = 0i128;
+    let mut n = 0i128;
+    while term != 0 {
+        moment_ratio += term;
+        term = mul_div_floor(env, term, b, SCALE * (n + 3));
+        n += 1;
+    }
+
+    let total_variance = pre_window + ln(env, moment_ratio);
+    black_price(env, spot, strike, total_variance, is_call)
+}
+
+// Position of x between grid points: (lower index, upper index, weight of the upper point).
+// Points outside the grid clamp to its edge.
+fn grid_position(env: &Env, points: &Vec<i128>, x: i128) -> (u32, u32, i128) {
+    let last = points.len() - 1;
+    if x <= points.get_unchecked(0) {
+        return (0, 0, 0);
+    }
+    if x >= points.get_unchecked(last) {
+        return (last, last, 0);
+    }
+    let mut upper = 1;
+    while points.get_unchecked(upper) <= x {
+        upper += 1;
+    }
+    let low = points.get_unchecked(upper - 1);
+    let high = points.get_unchecked(upper);
+    (
+        upper - 1,
+        upper,
+        mul_div_floor(env, x - low, SCALE, high - low),
+    )
+}
+
+fn lerp(env: &Env, a: i128, b: i128, weight: i128) -> i128 {
+    a + mul_div_floor(env, b - a, weight, SCALE)
+}
+
+// Bilinear interpolation on a moneyness x tenor vol grid stored tenor-major
+// (`vols[t * moneyness.len() + m]`), flat beyond the edges
+pub fn interpolate_vol(
+    env: &Env,
+    moneyness: &Vec<i128>,
+    tenors: &Vec<u64>,
+    vols: &Vec<i128>,
+    strike_moneyness: i128,
+    tenor: u64,
+) -> i128 {
+    let mut tenor_points = Vec::new(env);
+    for t in tenors.iter() {
+        tenor_points.push_back(t as i128);
+    }
+    let (m0, m1, mw) = grid_position(env, moneyness, strike_moneyness);
+    let (t0, t1, tw) = grid_position(env, &tenor_points, tenor as i128);
+
+    let width = moneyness.len();
+    let smile = |t: u32| {
+        lerp(
+            env,
+            vols.get_unchecked(t * width + m0),
+            vols.get_unchecked(t * width + m1),
+            mw,
+        )
+    };
+    lerp(env, smile(t0), smile(t1), tw)
+}
@@ -1,10 +1,127 @@
 #![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping)] // prices written as <units>_<1e7 decimals>
 use super::*;
 use soroban_sdk::{
-    testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation, MockAuth, MockAuthInvoke},
-    token, Address, Env, IntoVal, String,
+    contract, contractimpl,
+    testutils::{Address as _, Ledger},
+    token, vec,
+    xdr::ToXdr,
+    Address, BytesN, Env, String,
 };
 
+// Minimal SEP-40 oracle whose price history is set by the test
+#[contract]
+pub struct MockPriceFeed;
+
+#[contractimpl]
+impl MockPriceFeed {
+    pub fn set_price(env: Env, price: i128) {
+        let mut history: Vec<PriceData> = env
+            .storage()
+            .persistent()
+            .get(&symbol_short!("history"))
+            .unwrap_or(Vec::new(&env));
+        let record = PriceData {
+            price,
+            timestamp: env.ledger().timestamp(),
+        };
+        history.push_back(record.clone());
+        env.storage()
+            .persistent()
+            .set(&symbol_short!("history"), &history);
+
+        // Feeds keep one price per resolution period, the last one in it
+        let period = record.timestamp - record.timestamp % Self::resolution(env.clone()) as u64;
+        env.storage().persistent().set(&period, &record);
+    }
+}
+
+#[contractimpl]
+impl PriceFeedTrait for MockPriceFeed {
+    fn base(env: Env) -> Asset {
+        Asset::Other(Symbol::new(&env, "USD"))
+    }
+
+    fn assets(env: Env) -> Vec<Asset> {
+        vec![&env, Asset::Other(Symbol::new(&env, "XLM"))]
+    }
+
+    fn decimals(_env: Env) -> u32 {
+        7
+    }
+
+    fn resolution(_env: Env) -> u32 {
+        300
+    }
+
+    fn price(env: Env, _asset: Asset, timestamp: u64) -> Option<PriceData> {
+        env.storage().persistent().get(&timestamp)
+    }
+
+    // Most recent records first, as SEP-40 feeds return them
+    fn prices(env: Env, _asset: Asset, records: u32) -> Option<Vec<PriceData>> {
+        let history: Vec<PriceData> = env.storage().persistent().get(&symbol_short!("history"))?;
+        let mut result = Vec::new(&env);
+        for p in history.iter().rev().take(records as usize) {
+            result.push_back(p);
+        }
+        Some(result)
+    }
+
+    fn lastprice(env: Env, _asset: Asset) -> Option<PriceData> {
+        let history: Vec<PriceData> = env.storage().persistent().get(&symbol_short!("history"))?;
+        history.last()
+    }
+}
+
+// SEP-41 style token with configurable decimals (Stellar assets always use 7).
+// Kept in its own module so its `decimals` export doesn't clash with MockPriceFeed's.
+mod decimals_token {
+    use soroban_sdk::{contract, contractimpl, symbol_short, Address, Env};
+
+    #[contract]
+    pub struct MockDecimalsToken;
+
+    #[contractimpl]
+    impl MockDecimalsToken {
+        pub fn __constructor(env: Env, decimals: u32) {
+            env.storage()
+                .instance()
+                .set(&symbol_short!("decimals"), &decimals);
+        }
+
+        pub fn decimals(env: Env) -> u32 {
+            env.storage()
+                .instance()
+                .get(&symbol_short!("decimals"))
+                .unwrap()
+        }
+
+        pub fn mint(env: Env, to: Address, amount: i128) {
+            let balance = Self::balance(env.clone(), to.clone());
+            env.storage().instance().set(&to, &(balance + amount));
+        }
+
+        pub fn balance(env: Env, id: Address) -> i128 {
+            env.storage().instance().get(&id).unwrap_or(0)
+        }
+
+        pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+            from.require_auth();
+            let from_balance = Self::balance(env.clone(), from.clone());
+            if from_balance < amount {
+                panic!("insufficient balance");
+            }
+            env.storage()
+                .instance()
+                .set(&from, &(from_balance - amount));
+            Self::mint(env, to, amount);
+        }
+    }
+}
+
+use decimals_token::{MockDecimalsToken, MockDecimalsTokenClient};
+
 // Create a mock token contract for testing
 #[derive(Clone)]
 pub struct TestToken {
@@ -14,7 +131,9 @@ pub struct TestToken {
 
 impl TestToken {
     pub fn new(env: &Env, admin: &Address) -> Self {
-        let address = env.register_stellar_asset_contract(admin.clone());
+        let address = env
+            .register_stellar_asset_contract_v2(admin.clone())
+            .address();
         Self {
             address,
             env: env.clone(),
@@ -26,6 +145,10 @@ impl TestToken {
         token_client.mint(to, amount);
     }
 
+    pub fn balance(&self, id: &Address) -> i128 {
+        token::Client::new(&self.env, &self.address).balance(id)
+    }
+
     // pub fn balance(&self, id: &Address) -> i128 {
     //     let token_client = token::StellarAssetClient::new(&self.env, &self.address);
     //     token_client(id)
@@ -38,13 +161,23 @@ impl TestToken {
 }
 
 fn create_test_contract<'a>(e: &Env) -> OptionsContractClient<'a> {
-    OptionsContractClient::new(e, &e.register_contract(None, OptionsContract {}))
+    OptionsContractClient::new(e, &e.register(OptionsContract {}, ()))
 }
 
 fn create_token_contract(e: &Env, admin: &Address) -> TestToken {
     TestToken::new(e, admin)
 }
 
+fn create_decimals_token<'a>(e: &Env, decimals: u32) -> MockDecimalsTokenClient<'a> {
+    MockDecimalsTokenClient::new(e, &e.register(MockDecimalsToken, (decimals,)))
+}
+
+fn create_price_feed<'a>(e: &Env, price: i128) -> MockPriceFeedClient<'a> {
+    let feed = MockPriceFeedClient::new(e, &e.register(MockPriceFeed, ()));
+    feed.set_price(&price);
+    feed
+}
+
 #[test]
 fn test_initialize() {
     let env = Env::default();
@@ -84,8 +217,8 @@ fn test_add_liquidity_pool() {
     let admin = Address::generate(&env);
     let contract = create_test_contract(&env);
 
-    let stable_token = Address::generate(&env);
-    let underlying_asset = Address::generate(&env);
+    let stable_token = create_token_contract(&env, &admin).address;
+    let underlying_asset = create_token_contract(&env, &admin).address;
     let price_feed = Address::generate(&env);
     let pool_name = String::from_str(&env, "BTC/USDC Pool");
 
@@ -105,33 +238,43 @@ fn test_add_liquidity_pool() {
     assert_eq!(pool.underlying_asset, underlying_asset);
     assert_eq!(pool.price_feed, price_feed);
     assert_eq!(pool.name, pool_name);
-    assert_eq!(pool.is_active, true);
+    assert!(pool.is_active);
 
     // Verify pool counter was incremented
     assert_eq!(contract.get_pool_counter(), 1);
 }
 
 #[test]
-#[should_panic(expected = "HostError: Error(Contract, #14)")]
-fn test_add_duplicate_pool() {
+fn test_add_second_pool_for_pair() {
     let env = Env::default();
     env.mock_all_auths();
 
     let admin = Address::generate(&env);
     let contract = create_test_contract(&env);
 
-    let stable_token = Address::generate(&env);
-    let underlying_asset = Address::generate(&env);
+    let stable_token = create_token_contract(&env, &admin).address;
+    let underlying_asset = create_token_contract(&env, &admin).address;
     let price_feed = Address::generate(&env);
-    let pool_name = String::from_str(&env, "BTC/USDC Pool");
 
     contract.initialize(&admin);
 
-    // Add first pool
-    contract.add_liquidity_pool(&stable_token, &underlying_asset, &price_feed, &pool_name);
-
-    // Try to add duplicate pool - should panic
-    contract.add_liquidity_pool(&stable_token, &underlying_asset, &price_feed, &pool_name);
+    // Pairs can have several pools
+    let first = contract.add_liquidity_pool(
+        &stable_token,
+        &underlying_asset,
+        &price_feed,
+        &String::from_str(&env, "BTC/USDC Conservative"),
+    );
+    let second = contract.add_liquidity_pool(
+        &stable_token,
+        &underlying_asset,
+        &price_feed,
+        &String::from_str(&env, "BTC/USDC Aggressive"),
+    );
+    assert_eq!(
+        contract.get_pool_by_assets(&stable_token, &underlying_asset),
+        vec![&env, first, second]
+    );
 }
 
 #[test]
@@ -144,7 +287,7 @@ fn test_provide_liquidity() {
     let contract = create_test_contract(&env);
 
     let stable_token = create_token_contract(&env, &admin);
-    let underlying_asset = Address::generate(&env);
+    let underlying_asset = create_token_contract(&env, &admin).address;
     let price_feed = Address::generate(&env);
     let pool_name = String::from_str(&env, "BTC/USDC Pool");
 
@@ -158,19 +301,126 @@ fn test_provide_liquidity() {
     );
 
     // Mint tokens for provider
-    stable_token.mint(&provider, &10000);
+    stable_token.mint(&provider, &10_000_0000000);
 
     // Provide liquidity
-    let amount = 1000i128;
-    let shares = contract.provide_liquidity(&pool_id, &provider, &amount);
+    let amount = 1000_0000000i128;
+    let shares = contract.provide_liquidity(&pool_id, &provider, &amount, &0);
 
-    // For first provider, shares should equal amount (1:1 ratio)
-    assert_eq!(shares, amount);
+    // First provider gets 1:1 shares minus the permanently locked dead shares
+    assert_eq!(shares, amount - DEAD_SHARES);
 
     // Verify pool state
     assert_eq!(contract.get_pool_total_liquidity(&pool_id), amount);
     assert_eq!(contract.get_pool_total_lp_shares(&pool_id), amount);
-    assert_eq!(contract.get_pool_lp_shares(&pool_id, &provider), amount);
+    assert_eq!(
+        contract.get_pool_lp_shares(&pool_id, &provider),
+        amount - DEAD_SHARES
+    );
+
+    // Later deposits mint at the current share price
+    let shares = contract.provide_liquidity(&pool_id, &provider, &amount, &amount);
+    assert_eq!(shares, amount);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #26)")]
+fn test_provide_liquidity_min_shares_out() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &Address::generate(&env),
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    stable_token.mint(&provider, &1000_0000000);
+    let amount = 1000_0000000i128;
+    contract.provide_liquidity(&pool_id, &provider, &amount, &amount);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #25)")]
+fn test_provide_liquidity_below_minimum() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &Address::generate(&env),
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    stable_token.mint(&provider, &1000);
+    contract.provide_liquidity(&pool_id, &provider, &1000, &0);
+}
+
+#[test]
+fn test_deposit_limits_scale_with_decimals() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    // 18-decimal stable: the minimum deposit is 0.01 tokens and 0.0001 tokens of
+    // shares are locked, not 100_000 and 1000 raw units
+    let stable_token = create_decimals_token(&env, 18);
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &Address::generate(&env),
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    stable_token.mint(&provider, &1_000000000000000000);
+    let shares = contract.provide_liquidity(&pool_id, &provider, &1_000000000000000000, &0);
+    assert_eq!(shares, 1_000000000000000000 - 100000000000000);
+    assert_eq!(
+        contract.get_pool_total_lp_shares(&pool_id),
+        1_000000000000000000
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #25)")]
+fn test_deposit_minimum_scales_with_decimals() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_decimals_token(&env, 18);
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &Address::generate(&env),
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    // 1e15 raw units is only 0.001 tokens
+    stable_token.mint(&provider, &1_000000000000000);
+    contract.provide_liquidity(&pool_id, &provider, &1_000000000000000, &0);
 }
 
 #[test]
@@ -183,7 +433,7 @@ fn test_buy_call_option() {
     let contract = create_test_contract(&env);
 
     let stable_token = create_token_contract(&env, &admin);
-    let underlying_asset = Address::generate(&env);
+    let underlying_asset = create_token_contract(&env, &admin).address;
     let price_feed = Address::generate(&env);
     let pool_name = String::from_str(&env, "BTC/USDC Pool");
 
@@ -198,28 +448,17 @@ fn test_buy_call_option() {
 
     // Provide initial liquidity
     let provider = Address::generate(&env);
-    stable_token.mint(&provider, &100000);
-    contract.provide_liquidity(&pool_id, &provider, &50000);
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &5_000_0000000, &0);
 
     // Mint tokens for buyer (for premium)
-    stable_token.mint(&buyer, &10000);
+    stable_token.mint(&buyer, &100_0000000);
 
     // Buy call option
     let strike = 2100_0000000i128; // $2100
     let expiry = env.ledger().timestamp() + 86400; // 1 day from now
     let amount = 10_000_000i128; // 1 unit (1e7 scaling)
 
-    // Mock authorization for the buyer specifically for token transfer
-    env.mock_auths(&[MockAuth {
-        address: &buyer,
-        invoke: &MockAuthInvoke {
-            contract: &stable_token.address,
-            fn_name: "transfer",
-            args: (&buyer, &contract.address, 4200_0000000i128).into_val(&env), // Expected premium
-            sub_invokes: &[],
-        },
-    }]);
-
     let option_id = contract.buy_option(
         &pool_id,
         &buyer,
@@ -237,8 +476,9 @@ fn test_buy_call_option() {
     assert_eq!(option.strike, strike);
     assert_eq!(option.expiry, expiry);
     assert_eq!(option.amount, amount);
-    assert_eq!(option.is_active, true);
-    assert_eq!(option.is_exercised, false);
+    assert!(option.is_active);
+    assert!(!option.is_exercised);
+    assert_eq!(option.premium_paid, 42_0000000); // 2% of $2100
 
     // Verify collateral was locked
     let expected_collateral = strike * amount / 10_000_000;
@@ -365,8 +605,8 @@ fn test_get_all_pools() {
 
     // Add a few pools
     for _i in 0..3 {
-        let stable_token = Address::generate(&env);
-        let underlying_asset = Address::generate(&env);
+        let stable_token = create_token_contract(&env, &admin).address;
+        let underlying_asset = create_token_contract(&env, &admin).address;
         let price_feed = Address::generate(&env);
         let pool_name = String::from_str(&env, "Pool");
 
@@ -389,8 +629,8 @@ fn test_set_pool_status() {
     let admin = Address::generate(&env);
     let contract = create_test_contract(&env);
 
-    let stable_token = Address::generate(&env);
-    let underlying_asset = Address::generate(&env);
+    let stable_token = create_token_contract(&env, &admin).address;
+    let underlying_asset = create_token_contract(&env, &admin).address;
     let price_feed = Address::generate(&env);
     let pool_name = String::from_str(&env, "BTC/USDC Pool");
 
@@ -400,15 +640,3345 @@ fn test_set_pool_status() {
 
     // Pool should be active by default
     let pool = contract.get_pool(&pool_id);
-    assert_eq!(pool.is_active, true);
+    assert!(pool.is_active);
 
     // Deactivate pool
     contract.set_pool_status(&pool_id, &false);
     let pool = contract.get_pool(&pool_id);
-    assert_eq!(pool.is_active, false);
+    assert!(!pool.is_active);
 
     // Reactivate pool
     contract.set_pool_status(&pool_id, &true);
     let pool = contract.get_pool(&pool_id);
-    assert_eq!(pool.is_active, true);
+    assert!(pool.is_active);
+}
+
+#[test]
+fn test_series_registry() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let underlying_asset = create_token_contract(&env, &admin).address;
+    let price_feed = Address::generate(&env);
+    let pool_name = String::from_str(&env, "BTC/USDC Pool");
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &underlying_asset,
+        &price_feed,
+        &pool_name,
+    );
+
+    let provider = Address::generate(&env);
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+    stable_token.mint(&buyer, &1_000_0000000);
+
+    // List a weekly and a monthly series
+    let strike = 2000_0000000i128;
+    let weekly = env.ledger().timestamp() + 7 * 86400;
+    let monthly = env.ledger().timestamp() + 30 * 86400;
+    let weekly_id = contract.list_series(
+        &pool_id,
+        &OptionType::Call,
+        &strike,
+        &weekly,
+        &OptionStyle::American,
+    );
+    let monthly_id = contract.list_series(
+        &pool_id,
+        &OptionType::Put,
+        &strike,
+        &monthly,
+        &OptionStyle::European,
+    );
+    assert_eq!(contract.get_series_counter(), 2);
+    assert_eq!(contract.get_pool_series(&pool_id).len(), 2);
+
+    // Buy by series ID
+    let amount = 10_000_000i128;
+    let option_id = contract.buy_series_option(&weekly_id, &buyer, &amount);
+    contract.buy_series_option(&weekly_id, &buyer, &amount);
+
+    let option = contract.get_option(&option_id);
+    assert_eq!(option.series_id, Some(weekly_id));
+    assert_eq!(option.strike, strike);
+    assert_eq!(option.expiry, weekly);
+    assert_eq!(option.style, OptionStyle::American);
+    assert_eq!(contract.get_series_open_interest(&weekly_id), 2 * amount);
+    assert_eq!(contract.get_series_open_interest(&monthly_id), 0);
+
+    // Once the weekly expires it is archived on the next pool interaction
+    env.ledger().with_mut(|l| l.timestamp = weekly + 1);
+    contract.buy_series_option(&monthly_id, &buyer, &amount);
+
+    assert_eq!(contract.get_pool_series(&pool_id).len(), 1);
+    assert_eq!(contract.get_pool_archived_series(&pool_id).len(), 1);
+    assert!(contract.get_series(&weekly_id).is_archived);
+
+    // Expiring the option releases its open interest
+    contract.expire_option(&option_id);
+    assert_eq!(contract.get_series_open_interest(&weekly_id), amount);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #17)")]
+fn test_list_duplicate_series() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &create_token_contract(&env, &admin).address,
+        &create_token_contract(&env, &admin).address,
+        &Address::generate(&env),
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    let expiry = env.ledger().timestamp() + 86400;
+    let strike = 2000_0000000i128;
+    contract.list_series(
+        &pool_id,
+        &OptionType::Call,
+        &strike,
+        &expiry,
+        &OptionStyle::American,
+    );
+    contract.list_series(
+        &pool_id,
+        &OptionType::Call,
+        &strike,
+        &expiry,
+        &OptionStyle::American,
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #19)")]
+fn test_european_option_not_exercisable_before_expiry() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &Address::generate(&env),
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    let provider = Address::generate(&env);
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+    stable_token.mint(&buyer, &1_000_0000000);
+
+    let series_id = contract.list_series(
+        &pool_id,
+        &OptionType::Call,
+        &(2000_0000000i128),
+        &(env.ledger().timestamp() + 86400),
+        &OptionStyle::European,
+    );
+    let option_id = contract.buy_series_option(&series_id, &buyer, &10_000_000);
+
+    contract.exercise_option(&option_id);
+}
+
+#[test]
+fn test_european_option_settles_at_expiry_price() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    let provider = Address::generate(&env);
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+    stable_token.mint(&buyer, &1_000_0000000);
+
+    let expiry = env.ledger().timestamp() + 86400;
+    let series_id = contract.list_series(
+        &pool_id,
+        &OptionType::Call,
+        &(2000_0000000i128),
+        &expiry,
+        &OptionStyle::European,
+    );
+    let option_id = contract.buy_series_option(&series_id, &buyer, &1_0000000);
+
+    // Spot rallies after expiry; the holder still gets the payoff at the expiry print
+    env.ledger().with_mut(|l| l.timestamp = expiry - 100);
+    price_feed.set_price(&2100_0000000);
+    env.ledger().with_mut(|l| l.timestamp = expiry + 12 * 3600);
+    price_feed.set_price(&2500_0000000);
+
+    let balance_before = stable_token.balance(&buyer);
+    assert_eq!(contract.exercise_option(&option_id), 100_0000000);
+    assert_eq!(stable_token.balance(&buyer), balance_before + 100_0000000);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #10)")]
+fn test_physical_european_out_of_the_money_at_expiry() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let underlying_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &underlying_token.address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    let vault_id =
+        contract.create_vault(&pool_id, &VaultStrategy::CoveredCall, &1000, &100_0000000);
+    underlying_token.mint(&alice, &10_0000000);
+    contract.vault_deposit(&vault_id, &alice, &10_0000000);
+    let series = contract.get_series(&contract.roll_vault(&vault_id).unwrap());
+
+    stable_token.mint(&buyer, &20_000_0000000);
+    let option_id = contract.buy_vault_option(&vault_id, &buyer, &1_0000000);
+
+    // The 2,200 call expires out of the money; a later rally doesn't revive it
+    env.ledger().with_mut(|l| l.timestamp = series.expiry);
+    price_feed.set_price(&2100_0000000);
+    env.ledger()
+        .with_mut(|l| l.timestamp = series.expiry + 3600);
+    price_feed.set_price(&2500_0000000);
+    contract.exercise_option(&option_id);
+}
+
+#[test]
+fn test_roll_series() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    // Wednesday 2025-10-15 00:00 UTC
+    env.ledger().with_mut(|l| l.timestamp = 1760486400);
+
+    let admin = Address::generate(&env);
+    let contract = create_test_contract(&env);
+    let price_feed = create_price_feed(&env, 2013_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &create_token_contract(&env, &admin).address,
+        &create_token_contract(&env, &admin).address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    contract.set_series_schedule(
+        &pool_id,
+        &SeriesSchedule {
+            tick_size: 50_0000000,
+            strike_offsets_bps: vec![&env, 500, 1000],
+            weekly_expiries: 3,
+            monthly_expiries: 1,
+            style: OptionStyle::European,
+        },
+    );
+
+    // 5 strikes x 2 types x 3 expiries; the third Friday is also the monthly expiry
+    let created = contract.roll_series(&pool_id);
+    assert_eq!(created.len(), 30);
+
+    let friday = 1760688000; // 2025-10-17 08:00 UTC
+    let month_end = 1761897600; // 2025-10-31 08:00 UTC
+    let first = contract.get_series(&created.get(0).unwrap());
+    assert_eq!(first.expiry, friday);
+    assert_eq!(first.strike, 2000_0000000); // spot rounded to the 50 tick
+    assert_eq!(first.style, OptionStyle::European);
+
+    let mut strikes = Vec::new(&env);
+    let mut expiries = Vec::new(&env);
+    for series_id in created.iter() {
+        let series = contract.get_series(&series_id);
+        if !strikes.contains(series.strike) {
+            strikes.push_back(series.strike);
+        }
+        if !expiries.contains(series.expiry) {
+            expiries.push_back(series.expiry);
+        }
+    }
+    assert_eq!(
+        strikes,
+        vec![
+            &env,
+            2000_0000000,
+            2100_0000000,
+            1900_0000000,
+            2200_0000000,
+            1800_0000000
+        ]
+    );
+    assert_eq!(month_end, friday + 14 * 86400);
+    assert_eq!(expiries, vec![&env, friday, friday + 7 * 86400, month_end]);
+
+    // Rolling again lists nothing new
+    assert_eq!(contract.roll_series(&pool_id).len(), 0);
+
+    // After the first Friday the ladder rolls forward one week
+    env.ledger().with_mut(|l| l.timestamp = friday + 1);
+    let rolled = contract.roll_series(&pool_id);
+    assert_eq!(rolled.len(), 10);
+    assert_eq!(
+        contract.get_series(&rolled.get(0).unwrap()).expiry,
+        friday + 21 * 86400
+    );
+    assert_eq!(contract.get_pool_archived_series(&pool_id).len(), 10);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #21)")]
+fn test_series_schedule_too_large() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, _, pool_id, _) = perpetual_setup(&env);
+
+    contract.set_series_schedule(
+        &pool_id,
+        &SeriesSchedule {
+            tick_size: 50_0000000,
+            strike_offsets_bps: vec![&env, 500, 1000],
+            weekly_expiries: 4,
+            monthly_expiries: 2,
+            style: OptionStyle::European,
+        },
+    );
+}
+
+#[test]
+fn test_physical_settlement() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let stable_lp = Address::generate(&env);
+    let underlying_lp = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let underlying_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &underlying_token.address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    stable_token.mint(&stable_lp, &10_000_0000000);
+    underlying_token.mint(&underlying_lp, &10_0000000);
+    let stable_shares = contract.provide_liquidity(&pool_id, &stable_lp, &10_000_0000000, &0);
+    let underlying_shares =
+        contract.provide_underlying_liquidity(&pool_id, &underlying_lp, &10_0000000, &0);
+    assert_eq!(underlying_shares, 10_0000000 - DEAD_SHARES);
+
+    stable_token.mint(&buyer, &5_000_0000000);
+    underlying_token.mint(&buyer, &1_0000000);
+
+    let strike = 2000_0000000i128;
+    let expiry = env.ledger().timestamp() + 86400;
+    let amount = 1_0000000i128;
+
+    // Physical call: locks underlying, delivers it against the strike
+    let call_id = contract.buy_physical_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &strike,
+        &expiry,
+        &amount,
+    );
+    assert_eq!(contract.get_pool_locked_underlying(&pool_id), amount);
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 0);
+
+    let stable_before = stable_token.balance(&buyer);
+    assert_eq!(contract.exercise_option(&call_id), amount);
+    assert_eq!(stable_token.balance(&buyer), stable_before - strike);
+    assert_eq!(underlying_token.balance(&buyer), 2_0000000);
+    assert_eq!(contract.get_pool_locked_underlying(&pool_id), 0);
+    assert_eq!(contract.get_pool_underlying_liquidity(&pool_id), 9_0000000);
+    assert_eq!(contract.get_pool_underlying_proceeds(&pool_id), strike);
+
+    // Physical put: locks stable, takes underlying and pays the strike
+    let put_id = contract.buy_physical_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Put,
+        &strike,
+        &expiry,
+        &amount,
+    );
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), strike);
+
+    let stable_before = stable_token.balance(&buyer);
+    assert_eq!(contract.exercise_option(&put_id), strike);
+    assert_eq!(stable_token.balance(&buyer), stable_before + strike);
+    assert_eq!(underlying_token.balance(&buyer), 1_0000000);
+    // Both 40 premiums were booked to the stable side
+    assert_eq!(contract.get_pool_total_liquidity(&pool_id), 8_080_0000000);
+    assert_eq!(contract.get_pool_delivered_underlying(&pool_id), amount);
+    let mtm = contract.get_pool_mark_to_market(&pool_id);
+    assert_eq!(mtm.delivered_value, strike);
+    assert_eq!(mtm.nav, 8_080_0000000 + strike);
+
+    // Underlying LPs exit with the remaining inventory plus strike proceeds
+    let withdrawn =
+        contract.withdraw_underlying_liquidity(&pool_id, &underlying_lp, &underlying_shares);
+    assert_eq!(withdrawn, underlying_shares * 9_0000000 / 10_0000000);
+    assert_eq!(underlying_token.balance(&underlying_lp), withdrawn);
+    assert_eq!(
+        stable_token.balance(&underlying_lp),
+        underlying_shares * strike / 10_0000000
+    );
+
+    // Stable LPs exit with their stable plus the underlying taken in by the put
+    contract.withdraw_liquidity(&pool_id, &stable_lp, &stable_shares);
+    assert_eq!(
+        stable_token.balance(&stable_lp),
+        stable_shares * 8_080_0000000 / 10_000_0000000
+    );
+    assert_eq!(
+        underlying_token.balance(&stable_lp),
+        stable_shares * amount / 10_000_0000000
+    );
+}
+
+#[test]
+fn test_underlying_shares_priced_with_proceeds() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let underlying_lp = Address::generate(&env);
+    let late_lp = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let underlying_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &underlying_token.address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+    underlying_token.mint(&underlying_lp, &10_0000000);
+    contract.provide_underlying_liquidity(&pool_id, &underlying_lp, &10_0000000, &0);
+
+    // A 1,500 call is exercised: 1 underlying leaves and 1,500 of proceeds come in
+    stable_token.mint(&buyer, &5_000_0000000);
+    let call_id = contract.buy_physical_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &1500_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &1_0000000,
+    );
+    contract.exercise_option(&call_id);
+    assert_eq!(contract.get_pool_underlying_liquidity(&pool_id), 9_0000000);
+
+    // The pool holds 9 underlying plus proceeds worth 0.75 at 2,000, so 1 underlying
+    // buys 10 / 9.75 of the existing shares rather than 10 / 9
+    underlying_token.mint(&late_lp, &1_0000000);
+    let shares = contract.provide_underlying_liquidity(&pool_id, &late_lp, &1_0000000, &0);
+    assert_eq!(shares, 1_0000000 * 10_0000000 / 9_7500000);
+}
+
+#[test]
+fn test_covered_call_vault_epoch() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let underlying_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &underlying_token.address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    // 10% OTM calls rounded to $100
+    let vault_id =
+        contract.create_vault(&pool_id, &VaultStrategy::CoveredCall, &1000, &100_0000000);
+
+    underlying_token.mint(&alice, &10_0000000);
+    contract.vault_deposit(&vault_id, &alice, &10_0000000);
+    assert_eq!(contract.get_vault(&vault_id).pending_deposits, 10_0000000);
+    assert_eq!(contract.get_vault_shares(&vault_id, &alice), 0);
+
+    // First roll mints shares and starts selling the epoch series
+    let series_id = contract.roll_vault(&vault_id).unwrap();
+    let series = contract.get_series(&series_id);
+    assert_eq!(series.opt_type, OptionType::Call);
+    assert_eq!(series.strike, 2200_0000000);
+    assert_eq!(series.style, OptionStyle::European);
+    assert_eq!(contract.get_vault_shares(&vault_id, &alice), 10_0000000);
+
+    stable_token.mint(&buyer, &20_000_0000000);
+    let option_id = contract.buy_vault_option(&vault_id, &buyer, &5_0000000);
+    let option = contract.get_option(&option_id);
+    assert_eq!(option.writer, OptionWriter::Vault(vault_id));
+    assert_eq!(option.premium_paid, 220_0000000);
+
+    let vault = contract.get_vault(&vault_id);
+    assert_eq!(vault.locked, 5_0000000);
+    assert_eq!(vault.total_proceeds, 220_0000000);
+    assert_eq!(contract.get_pool_locked_underlying(&pool_id), 0);
+
+    // Bob's deposit and Alice's withdrawal wait for the epoch boundary
+    underlying_token.mint(&bob, &4_0000000);
+    contract.vault_deposit(&vault_id, &bob, &4_0000000);
+    contract.vault_request_withdrawal(&vault_id, &alice, &10_0000000);
+
+    env.ledger().with_mut(|l| l.timestamp = series.expiry);
+    price_feed.set_price(&2300_0000000);
+    contract.exercise_option(&option_id);
+    assert_eq!(underlying_token.balance(&buyer), 5_0000000);
+
+    let vault = contract.get_vault(&vault_id);
+    assert_eq!(vault.locked, 0);
+    assert_eq!(vault.total_assets, 5_0000000);
+    assert_eq!(vault.total_proceeds, 220_0000000 + 11_000_0000000);
+
+    // Roll once the settlement window has closed
+    env.ledger()
+        .with_mut(|l| l.timestamp = series.expiry + 86400 + 1);
+    contract.roll_vault(&vault_id);
+
+    assert_eq!(underlying_token.balance(&alice), 5_0000000);
+    assert_eq!(stable_token.balance(&alice), 11_220_0000000);
+    assert_eq!(contract.get_vault_shares(&vault_id, &bob), 4_0000000);
+
+    let vault = contract.get_vault(&vault_id);
+    assert_eq!(vault.epoch, 2);
+    assert_eq!(vault.total_assets, 4_0000000);
+    assert_eq!(vault.pending_deposits, 0);
+    assert_eq!(contract.get_vault_withdrawal_queue(&vault_id).len(), 0);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #23)")]
+fn test_vault_roll_during_epoch() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let contract = create_test_contract(&env);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &create_token_contract(&env, &admin).address,
+        &create_token_contract(&env, &admin).address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    let vault_id = contract.create_vault(
+        &pool_id,
+        &VaultStrategy::CashSecuredPut,
+        &1000,
+        &100_0000000,
+    );
+    contract.roll_vault(&vault_id);
+    contract.roll_vault(&vault_id);
+}
+
+#[test]
+fn test_vault_rolls_while_pool_winds_down() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let underlying_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &underlying_token.address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    let vault_id =
+        contract.create_vault(&pool_id, &VaultStrategy::CoveredCall, &1000, &100_0000000);
+    underlying_token.mint(&alice, &10_0000000);
+    contract.vault_deposit(&vault_id, &alice, &10_0000000);
+    let series = contract.get_series(&contract.roll_vault(&vault_id).unwrap());
+
+    // Bob's deposit and Alice's exit are queued, then the pool starts winding down
+    underlying_token.mint(&bob, &4_0000000);
+    contract.vault_deposit(&vault_id, &bob, &4_0000000);
+    contract.vault_request_withdrawal(&vault_id, &alice, &10_0000000);
+    contract.wind_down_pool(&pool_id);
+
+    // The epoch still closes: Alice is paid, Bob is refunded and nothing new is listed
+    env.ledger()
+        .with_mut(|l| l.timestamp = series.expiry + 86400 + 1);
+    assert_eq!(contract.roll_vault(&vault_id), None);
+    assert_eq!(underlying_token.balance(&alice), 10_0000000);
+    assert_eq!(underlying_token.balance(&bob), 4_0000000);
+
+    let vault = contract.get_vault(&vault_id);
+    assert_eq!(vault.series_id, None);
+    assert_eq!(vault.total_shares, 0);
+    assert_eq!(vault.pending_deposits, 0);
+    assert_eq!(contract.get_vault_deposit_queue(&vault_id).len(), 0);
+    assert_eq!(contract.get_vault_withdrawal_queue(&vault_id).len(), 0);
+}
+
+#[test]
+fn test_withdrawal_queue() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &create_price_feed(&env, 1000_0000000).address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    stable_token.mint(&alice, &3_000_0000000);
+    stable_token.mint(&bob, &1_000_0000000);
+    stable_token.mint(&buyer, &100_0000000);
+    let alice_shares = contract.provide_liquidity(&pool_id, &alice, &3_000_0000000, &0);
+    let bob_shares = contract.provide_liquidity(&pool_id, &bob, &1_000_0000000, &0);
+
+    // Half the pool is locked behind a far out-of-the-money option; its 40 premium
+    // lifts the share price by 1%
+    let expiry = env.ledger().timestamp() + 86400;
+    let option_id = contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &(2000_0000000i128),
+        &expiry,
+        &10_000_000,
+    );
+
+    contract.request_withdrawal(&pool_id, &alice, &alice_shares);
+    contract.request_withdrawal(&pool_id, &bob, &bob_shares);
+    assert_eq!(contract.get_pool_lp_shares(&pool_id, &alice), 0);
+    assert_eq!(
+        contract.get_pool_queued_shares(&pool_id),
+        alice_shares + bob_shares
+    );
+    assert_eq!(
+        contract.get_pool_reserved_liquidity(&pool_id),
+        2_040_0000000
+    );
+
+    let alice_estimate = contract.get_withdrawal_estimate(&pool_id, &alice);
+    assert_eq!(alice_estimate.position, 0);
+    assert_eq!(alice_estimate.queued_value, alice_shares * 101 / 100);
+    assert_eq!(alice_estimate.fillable_now, 2_040_0000000);
+
+    let bob_estimate = contract.get_withdrawal_estimate(&pool_id, &bob);
+    assert_eq!(bob_estimate.position, 1);
+    assert_eq!(bob_estimate.value_ahead, alice_shares * 101 / 100);
+    assert_eq!(bob_estimate.fillable_now, 0);
+
+    // Head of the queue is partially filled from unlocked liquidity
+    let paid = contract.process_withdrawals(&pool_id);
+    assert!((paid - 2_040_0000000).abs() <= 1);
+    assert_eq!(stable_token.balance(&alice), paid);
+    assert_eq!(contract.get_withdrawal_queue(&pool_id).len(), 2);
+
+    // Expiry unlocks the rest of the collateral for the queue
+    env.ledger().with_mut(|l| l.timestamp = expiry + 1);
+    contract.expire_option(&option_id);
+    contract.process_withdrawals(&pool_id);
+
+    assert!((stable_token.balance(&alice) - alice_shares * 101 / 100).abs() <= 1);
+    assert!((stable_token.balance(&bob) - bob_shares * 101 / 100).abs() <= 1);
+    assert_eq!(contract.get_withdrawal_queue(&pool_id).len(), 0);
+    assert_eq!(contract.get_pool_queued_shares(&pool_id), 0);
+    assert_eq!(contract.get_pool_total_lp_shares(&pool_id), DEAD_SHARES);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #5)")]
+fn test_queued_liquidity_not_available_for_options() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &Address::generate(&env),
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    stable_token.mint(&provider, &4_000_0000000);
+    stable_token.mint(&buyer, &100_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &4_000_0000000, &0);
+    contract.request_withdrawal(&pool_id, &provider, &3_000_0000000);
+
+    contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Put,
+        &(2000_0000000i128),
+        &(env.ledger().timestamp() + 86400),
+        &10_000_000,
+    );
+}
+
+#[test]
+fn test_withdrawals_wait_while_nav_is_negative() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, _, pool_id, buyer) = perpetual_setup(&env);
+
+    let provider = Address::generate(&env);
+    stable_token.mint(&provider, &1_000_0000000);
+    let shares = contract.provide_liquidity(&pool_id, &provider, &1_000_0000000, &0);
+    contract.request_withdrawal(&pool_id, &provider, &shares);
+
+    // A loss leaves less liquidity than the open option is worth
+    contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 7 * 86400),
+        &1_0000000,
+    );
+    set_pool_liquidity(&env, &contract, pool_id, 10_0000000);
+    assert!(contract.get_pool_mark_to_market(&pool_id).nav < 0);
+
+    let total_shares = contract.get_pool_total_lp_shares(&pool_id);
+    assert_eq!(contract.process_withdrawals(&pool_id), 0);
+    assert_eq!(contract.get_withdrawal_queue(&pool_id).len(), 1);
+    assert_eq!(contract.get_pool_queued_shares(&pool_id), shares);
+    assert_eq!(contract.get_pool_total_lp_shares(&pool_id), total_shares);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #5)")]
+fn test_withdraw_while_nav_is_negative() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, _, pool_id, buyer) = perpetual_setup(&env);
+
+    let provider = Address::generate(&env);
+    stable_token.mint(&provider, &1_000_0000000);
+    let shares = contract.provide_liquidity(&pool_id, &provider, &1_000_0000000, &0);
+
+    contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 7 * 86400),
+        &1_0000000,
+    );
+    set_pool_liquidity(&env, &contract, pool_id, 10_0000000);
+
+    // Burning the shares for nothing is refused rather than wiping out the position
+    contract.withdraw_liquidity(&pool_id, &provider, &shares);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #11)")]
+fn test_withdraw_zero_shares() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, _, pool_id, _) = perpetual_setup(&env);
+
+    contract.withdraw_liquidity(&pool_id, &Address::generate(&env), &0);
+}
+
+// Half fill a pool's open option list with the smallest trades allowed
+fn buy_small_options(contract: &OptionsContractClient, buyer: &Address, pool_id: u64, expiry: u64) {
+    for _ in 0..50 {
+        contract.buy_option(
+            &pool_id,
+            buyer,
+            &OptionType::Call,
+            &2000_0000000,
+            &expiry,
+            &100_000,
+        );
+    }
+}
+
+#[test]
+fn test_pool_open_options_paged() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, _, pool_id, buyer) = perpetual_setup(&env);
+
+    let expiry = env.ledger().timestamp() + 7 * 86400;
+    buy_small_options(&contract, &buyer, pool_id, expiry);
+
+    // Past half full, an option locking a fair share of the capacity left still fits
+    contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+    );
+
+    let first_page = contract.get_pool_mark_to_market_page(&pool_id, &0, &30);
+    let second_page = contract.get_pool_mark_to_market_page(&pool_id, &30, &30);
+    assert_eq!(first_page.open_options + second_page.open_options, 51);
+    assert_eq!(
+        first_page.option_liability + second_page.option_liability,
+        contract.get_pool_mark_to_market(&pool_id).option_liability
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #28)")]
+fn test_small_option_in_crowded_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, _, pool_id, buyer) = perpetual_setup(&env);
+
+    // Filling the rest of the list with dust would shut the pool off cheaply
+    let expiry = env.ledger().timestamp() + 7 * 86400;
+    buy_small_options(&contract, &buyer, pool_id, expiry);
+    contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &100_000,
+    );
+}
+
+#[test]
+fn test_fixed_point_vectors() {
+    use fixed_point::{checked_mul_div_ceil, checked_mul_div_floor};
+
+    // (a, b, d, floor(a * b / d), ceil(a * b / d))
+    type Vector = (i128, i128, i128, Option<i128>, Option<i128>);
+    let vectors: [Vector; 14] = [
+        (7, 3, 2, Some(10), Some(11)),
+        (-7, 3, 2, Some(-11), Some(-10)),
+        (7, -3, -2, Some(10), Some(11)),
+        (6, 4, 3, Some(8), Some(8)),
+        (1, 1, 3, Some(0), Some(1)),
+        (-1, 1, 3, Some(-1), Some(0)),
+        (
+            2100_0000000,
+            1_5000000,
+            SCALE,
+            Some(3150_0000000),
+            Some(3150_0000000),
+        ),
+        (2100_0000000, 1, SCALE, Some(2100), Some(2100)),
+        (3, 200, BPS, Some(0), Some(1)),
+        // Intermediate products beyond i128 are fine when the result fits
+        (i128::MAX, 2, 2, Some(i128::MAX), Some(i128::MAX)),
+        (
+            i128::MAX,
+            i128::MAX,
+            i128::MAX,
+            Some(i128::MAX),
+            Some(i128::MAX),
+        ),
+        (
+            10i128.pow(30),
+            10i128.pow(30),
+            10i128.pow(25),
+            Some(10i128.pow(35)),
+            Some(10i128.pow(35)),
+        ),
+        // Results that do not fit, and division by zero, are rejected
+        (i128::MAX, 2, 1, None, None),
+        (1, 1, 0, None, None),
+    ];
+
+    for (a, b, d, floor, ceil) in vectors {
+        assert_eq!(
+            checked_mul_div_floor(a, b, d),
+            floor,
+            "floor({a} * {b} / {d})"
+        );
+        assert_eq!(checked_mul_div_ceil(a, b, d), ceil, "ceil({a} * {b} / {d})");
+    }
+
+    assert_eq!(checked_mul_div_floor(i128::MIN, 1, 1), Some(i128::MIN));
+    assert_eq!(checked_mul_div_floor(i128::MIN, -1, 1), None);
+    assert_eq!(checked_mul_div_ceil(i128::MIN, 3, 3), Some(i128::MIN));
+    assert_eq!(
+        checked_mul_div_floor(i128::MAX, i128::MAX, i128::MAX - 1),
+        None
+    );
+}
+
+#[test]
+fn test_checked_arithmetic() {
+    let env = Env::default();
+
+    assert_eq!(fixed_point::add(&env, i128::MAX - 1, 1), i128::MAX);
+    assert_eq!(fixed_point::sub(&env, i128::MIN + 1, 1), i128::MIN);
+    assert_eq!(fixed_point::mul(&env, -3, 1_0000000), -3_0000000);
+    assert_eq!(
+        fixed_point::round_to_tick(&env, 2024_9999999, 50_0000000),
+        2000_0000000
+    );
+    assert_eq!(
+        fixed_point::round_to_tick(&env, 2025_0000000, 50_0000000),
+        2050_0000000
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #27)")]
+fn test_checked_add_overflow() {
+    let env = Env::default();
+    fixed_point::add(&env, i128::MAX, 1);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #27)")]
+fn test_checked_mul_overflow() {
+    let env = Env::default();
+    fixed_point::mul(&env, i128::MAX / 2, 3);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #27)")]
+fn test_collateral_overflow() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &create_token_contract(&env, &admin).address,
+        &create_token_contract(&env, &admin).address,
+        &Address::generate(&env),
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    contract.buy_option(
+        &pool_id,
+        &Address::generate(&env),
+        &OptionType::Call,
+        &(i128::MAX / 2),
+        &(env.ledger().timestamp() + 86400),
+        &(10 * SCALE),
+    );
+}
+
+#[test]
+fn test_fractional_option_amounts() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+    stable_token.mint(&buyer, &100_0000000);
+
+    // 0.25 units: premium, collateral and payoff all scale with the amount
+    let option_id = contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &1800_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &2_500_000,
+    );
+    let option = contract.get_option(&option_id);
+    assert_eq!(option.premium_paid, 9_0000000); // 2% of $1800 * 0.25
+    assert_eq!(option.collateral, 450_0000000);
+    assert_eq!(stable_token.balance(&buyer), 91_0000000);
+
+    price_feed.set_price(&2200_0000000);
+    let payoff = contract.exercise_option(&option_id);
+    assert_eq!(payoff, 100_0000000); // ($2200 - $1800) * 0.25
+    assert_eq!(stable_token.balance(&buyer), 191_0000000);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #28)")]
+fn test_option_below_minimum_trade_size() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &Address::generate(&env),
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+
+    contract.buy_option(
+        &pool_id,
+        &Address::generate(&env),
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &99_999,
+    );
+}
+
+#[test]
+fn test_token_decimals() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let stable_lp = Address::generate(&env);
+    let underlying_lp = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    // USDC-like stable with 6 decimals, bridged BTC with 8
+    let stable_token = create_decimals_token(&env, 6);
+    let underlying_token = create_decimals_token(&env, 8);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &underlying_token.address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+    let pool = contract.get_pool(&pool_id);
+    assert_eq!(pool.stable_decimals, 6);
+    assert_eq!(pool.underlying_decimals, 8);
+
+    stable_token.mint(&stable_lp, &10_000_000000);
+    underlying_token.mint(&underlying_lp, &10_00000000);
+    contract.provide_liquidity(&pool_id, &stable_lp, &10_000_000000, &0);
+    contract.provide_underlying_liquidity(&pool_id, &underlying_lp, &10_00000000, &0);
+    stable_token.mint(&buyer, &5_000_000000);
+
+    let expiry = env.ledger().timestamp() + 86400;
+
+    // Cash call on 0.25 BTC: prices stay 1e7-scaled, token amounts use 6 decimals
+    let cash_id = contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &1800_0000000,
+        &expiry,
+        &2_500_000,
+    );
+    let option = contract.get_option(&cash_id);
+    assert_eq!(option.premium_paid, 9_000000);
+    assert_eq!(option.collateral, 450_000000);
+
+    // Physical call on 1 BTC locks 1e8 underlying units
+    let physical_id = contract.buy_physical_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+    );
+    assert_eq!(contract.get_option(&physical_id).collateral, 1_00000000);
+    assert_eq!(contract.get_pool_locked_underlying(&pool_id), 1_00000000);
+    assert_eq!(
+        stable_token.balance(&buyer),
+        5_000_000000 - 9_000000 - 40_000000
+    );
+
+    price_feed.set_price(&2200_0000000);
+    assert_eq!(contract.exercise_option(&cash_id), 100_000000);
+    assert_eq!(contract.exercise_option(&physical_id), 1_00000000);
+
+    assert_eq!(underlying_token.balance(&buyer), 1_00000000);
+    assert_eq!(
+        stable_token.balance(&buyer),
+        5_000_000000 - 9_000000 - 40_000000 + 100_000000 - 2000_000000
+    );
+    assert_eq!(contract.get_pool_underlying_proceeds(&pool_id), 2000_000000);
+}
+
+#[test]
+fn test_strategy_orders() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let trader = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 2100_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+    stable_token.mint(&trader, &2_000_0000000);
+
+    let expiry = env.ledger().timestamp() + 86400;
+    let leg = |opt_type: OptionType, side: PositionSide, strike: i128| StrategyLeg {
+        opt_type,
+        side,
+        strike,
+        expiry,
+        amount: 1_0000000,
+    };
+
+    // Call spread: the pool only locks the width of the spread
+    let spread_id = contract.buy_strategy(
+        &pool_id,
+        &trader,
+        &vec![
+            &env,
+            leg(OptionType::Call, PositionSide::Long, 2000_0000000),
+            leg(OptionType::Call, PositionSide::Short, 2200_0000000),
+        ],
+    );
+    let spread = contract.get_strategy(&spread_id);
+    assert_eq!(
+        contract.get_strategy_leg(&spread_id, &0).premium,
+        40_0000000
+    );
+    assert_eq!(
+        contract.get_strategy_leg(&spread_id, &1).premium,
+        -44_0000000
+    );
+    assert_eq!(spread.net_premium, -4_0000000);
+    assert_eq!(spread.pool_collateral, 200_0000000);
+    assert_eq!(spread.owner_collateral, 0);
+    assert_eq!(stable_token.balance(&trader), 2_004_0000000);
+
+    // Short straddle: the trader posts the max loss of the package
+    let straddle_id = contract.buy_strategy(
+        &pool_id,
+        &trader,
+        &vec![
+            &env,
+            leg(OptionType::Call, PositionSide::Short, 2000_0000000),
+            leg(OptionType::Put, PositionSide::Short, 2000_0000000),
+        ],
+    );
+    let straddle = contract.get_strategy(&straddle_id);
+    assert_eq!(straddle.net_premium, -80_0000000);
+    assert_eq!(straddle.pool_collateral, 0);
+    assert_eq!(straddle.owner_collateral, 2000_0000000);
+    assert_eq!(stable_token.balance(&trader), 84_0000000);
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 200_0000000);
+    assert_eq!(contract.get_pool_total_liquidity(&pool_id), 9_916_0000000);
+
+    // After expiry anyone can settle, at the expiry price
+    env.ledger().with_mut(|l| l.timestamp = expiry);
+    price_feed.set_price(&2300_0000000);
+    env.ledger().with_mut(|l| l.timestamp = expiry + 3600);
+    price_feed.set_price(&2500_0000000);
+    assert_eq!(contract.settle_strategy(&spread_id), 200_0000000);
+    assert_eq!(contract.settle_strategy(&straddle_id), 1700_0000000);
+
+    assert_eq!(stable_token.balance(&trader), 1_984_0000000);
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 0);
+    assert_eq!(contract.get_pool_total_liquidity(&pool_id), 10_016_0000000);
+    assert!(!contract.get_strategy(&spread_id).is_active);
+    assert_eq!(
+        contract.get_strategy_leg(&straddle_id, &1).settlement_price,
+        2300_0000000
+    );
+}
+
+#[test]
+fn test_calendar_strategy_settles_each_leg() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let trader = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+    stable_token.mint(&trader, &5_000_0000000);
+
+    let near = env.ledger().timestamp() + 86400;
+    let far = env.ledger().timestamp() + 7 * 86400;
+    let leg = |side: PositionSide, expiry: u64| StrategyLeg {
+        opt_type: OptionType::Call,
+        side,
+        strike: 2000_0000000,
+        expiry,
+        amount: 1_0000000,
+    };
+
+    // Each expiry settles at its own price, so the legs don't offset in the collateral
+    let calendar_id = contract.buy_strategy(
+        &pool_id,
+        &trader,
+        &vec![
+            &env,
+            leg(PositionSide::Short, near),
+            leg(PositionSide::Long, far),
+        ],
+    );
+    let calendar = contract.get_strategy(&calendar_id);
+    assert_eq!(calendar.pool_collateral, 2000_0000000);
+    assert_eq!(calendar.owner_collateral, 2000_0000000);
+    assert_eq!(calendar.expiry, far);
+
+    let closed_id =
+        contract.buy_strategy(&pool_id, &trader, &vec![&env, leg(PositionSide::Long, far)]);
+
+    // The near leg settles at its expiry price; the package stays open for the far leg
+    env.ledger().with_mut(|l| l.timestamp = near);
+    price_feed.set_price(&2300_0000000);
+    env.ledger().with_mut(|l| l.timestamp = near + 3600);
+    price_feed.set_price(&2500_0000000);
+    assert_eq!(contract.settle_strategy(&calendar_id), 0);
+
+    let near_leg = contract.get_strategy_leg(&calendar_id, &0);
+    assert!(near_leg.is_settled);
+    assert_eq!(near_leg.settlement_price, 2300_0000000);
+    assert_eq!(near_leg.payoff, -300_0000000);
+    assert!(!contract.get_strategy_leg(&calendar_id, &1).is_settled);
+    assert!(contract.get_strategy(&calendar_id).is_active);
+
+    // The owner can close the rest early at the current price
+    assert_eq!(contract.close_strategy(&closed_id), 500_0000000);
+
+    env.ledger().with_mut(|l| l.timestamp = far);
+    price_feed.set_price(&2100_0000000);
+    assert_eq!(contract.settle_strategy(&calendar_id), 1800_0000000);
+    assert_eq!(
+        contract.get_strategy_leg(&calendar_id, &1).payoff,
+        100_0000000
+    );
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 0);
+}
+
+#[test]
+fn test_lapsed_strategy_legs() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, price_feed, pool_id, buyer) = perpetual_setup(&env);
+
+    let expiry = env.ledger().timestamp() + 86400;
+    let leg = |opt_type: OptionType, side: PositionSide, strike: i128| StrategyLeg {
+        opt_type,
+        side,
+        strike,
+        expiry,
+        amount: 1000000,
+    };
+    let strategy_id = contract.buy_strategy(
+        &pool_id,
+        &buyer,
+        &vec![
+            &env,
+            leg(OptionType::Call, PositionSide::Long, 1800_0000000),
+            leg(OptionType::Put, PositionSide::Short, 2100_0000000),
+        ],
+    );
+    let strategy = contract.get_strategy(&strategy_id);
+
+    // Both legs finish in the money, but nobody settles inside the window
+    env.ledger().with_mut(|l| l.timestamp = expiry);
+    price_feed.set_price(&1900_0000000);
+    env.ledger().with_mut(|l| l.timestamp = expiry + 86400 + 1);
+
+    // The long call is forfeited; the short put still costs the owner its 20
+    let balance_before = stable_token.balance(&buyer);
+    contract.settle_strategy(&strategy_id);
+    assert_eq!(contract.get_strategy_leg(&strategy_id, &0).payoff, 0);
+    assert_eq!(
+        contract.get_strategy_leg(&strategy_id, &1).payoff,
+        -20_0000000
+    );
+    assert_eq!(
+        stable_token.balance(&buyer),
+        balance_before + strategy.owner_collateral - 20_0000000
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #29)")]
+fn test_strategy_without_legs() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &create_token_contract(&env, &admin).address,
+        &create_token_contract(&env, &admin).address,
+        &Address::generate(&env),
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    contract.buy_strategy(&pool_id, &Address::generate(&env), &Vec::new(&env));
+}
+
+#[test]
+fn test_peer_to_peer_option() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let writer = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    stable_token.mint(&writer, &5_000_0000000);
+    stable_token.mint(&buyer, &100_0000000);
+    let expiry = env.ledger().timestamp() + 86400;
+
+    // The writer escrows strike-sized collateral and asks 50 for the call
+    let order_id = contract.write_option(
+        &pool_id,
+        &writer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+        &50_0000000,
+    );
+    assert_eq!(
+        contract.get_option_order(&order_id).collateral,
+        2000_0000000
+    );
+    assert_eq!(stable_token.balance(&writer), 3_000_0000000);
+
+    let option_id = contract.fill_option_order(&order_id, &buyer);
+    let option = contract.get_option(&option_id);
+    assert_eq!(option.writer, OptionWriter::Account(writer.clone()));
+    assert_eq!(option.premium_paid, 50_0000000);
+    assert!(!contract.get_option_order(&order_id).is_open);
+    assert_eq!(stable_token.balance(&writer), 3_050_0000000);
+    assert_eq!(stable_token.balance(&buyer), 50_0000000);
+
+    // The payoff comes out of the writer's escrow, which returns the rest
+    price_feed.set_price(&2300_0000000);
+    assert_eq!(contract.exercise_option(&option_id), 300_0000000);
+    assert_eq!(stable_token.balance(&buyer), 350_0000000);
+    assert_eq!(stable_token.balance(&writer), 4_750_0000000);
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 0);
+
+    // Unfilled orders can be cancelled; filled ones expiring refund everything
+    let cancelled_id = contract.write_option(
+        &pool_id,
+        &writer,
+        &OptionType::Put,
+        &1000_0000000,
+        &expiry,
+        &1_0000000,
+        &10_0000000,
+    );
+    contract.cancel_option_order(&cancelled_id);
+    assert_eq!(stable_token.balance(&writer), 4_750_0000000);
+
+    let expiring_id = contract.write_option(
+        &pool_id,
+        &writer,
+        &OptionType::Put,
+        &1000_0000000,
+        &expiry,
+        &1_0000000,
+        &10_0000000,
+    );
+    let expiring_option = contract.fill_option_order(&expiring_id, &buyer);
+    env.ledger().with_mut(|l| l.timestamp = expiry + 1);
+    contract.expire_option(&expiring_option);
+    assert_eq!(stable_token.balance(&writer), 4_760_0000000);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #31)")]
+fn test_fill_cancelled_order() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let writer = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &Address::generate(&env),
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    stable_token.mint(&writer, &2_000_0000000);
+    let order_id = contract.write_option(
+        &pool_id,
+        &writer,
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &1_0000000,
+        &50_0000000,
+    );
+    contract.cancel_option_order(&order_id);
+
+    contract.fill_option_order(&order_id, &Address::generate(&env));
+}
+
+fn sign_quote(
+    env: &Env,
+    contract: &OptionsContractClient,
+    key: &ed25519_dalek::SigningKey,
+    quote: &OptionQuote,
+) -> BytesN<64> {
+    use ed25519_dalek::Signer;
+    extern crate std;
+
+    let message = (contract.address.clone(), quote.clone()).to_xdr(env);
+    let message: std::vec::Vec<u8> = message.iter().collect();
+    BytesN::from_array(env, &key.sign(&message).to_bytes())
+}
+
+fn quote_setup<'a>(
+    env: &Env,
+) -> (
+    OptionsContractClient<'a>,
+    TestToken,
+    ed25519_dalek::SigningKey,
+    OptionQuote,
+) {
+    let admin = Address::generate(env);
+    let provider = Address::generate(env);
+    let contract = create_test_contract(env);
+    let stable_token = create_token_contract(env, &admin);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(env, &admin).address,
+        &Address::generate(env),
+        &String::from_str(env, "BTC/USDC Pool"),
+    );
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+
+    let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+    contract.add_pool_quoter(
+        &pool_id,
+        &BytesN::from_array(env, &key.verifying_key().to_bytes()),
+    );
+
+    let quote = OptionQuote {
+        pool_id,
+        opt_type: OptionType::Call,
+        strike: 2000_0000000,
+        expiry: env.ledger().timestamp() + 86400,
+        amount: 2_0000000,
+        premium: 55_0000000,
+        valid_until: env.ledger().timestamp() + 60,
+        nonce: 1,
+    };
+    (contract, stable_token, key, quote)
+}
+
+#[test]
+fn test_buy_option_with_quote() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (contract, stable_token, key, quote) = quote_setup(&env);
+    let quoter = BytesN::from_array(&env, &key.verifying_key().to_bytes());
+    let buyer = Address::generate(&env);
+    stable_token.mint(&buyer, &100_0000000);
+
+    let signature = sign_quote(&env, &contract, &key, &quote);
+    let option_id = contract.buy_option_with_quote(&buyer, &quote, &quoter, &signature);
+
+    // Quoted premium replaces the 80 the model would charge
+    let option = contract.get_option(&option_id);
+    assert_eq!(option.premium_paid, 55_0000000);
+    assert_eq!(option.collateral, 4000_0000000);
+    assert_eq!(stable_token.balance(&buyer), 45_0000000);
+    assert!(contract.is_quote_nonce_used(&quoter, &1));
+    assert!(!contract.is_quote_nonce_used(&quoter, &2));
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #34)")]
+fn test_quote_nonce_replay() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (contract, stable_token, key, quote) = quote_setup(&env);
+    let quoter = BytesN::from_array(&env, &key.verifying_key().to_bytes());
+    let buyer = Address::generate(&env);
+    stable_token.mint(&buyer, &200_0000000);
+
+    let signature = sign_quote(&env, &contract, &key, &quote);
+    contract.buy_option_with_quote(&buyer, &quote, &quoter, &signature);
+    contract.buy_option_with_quote(&buyer, &quote, &quoter, &signature);
+}
+
+#[test]
+#[should_panic]
+fn test_quote_with_tampered_premium() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (contract, stable_token, key, mut quote) = quote_setup(&env);
+    let quoter = BytesN::from_array(&env, &key.verifying_key().to_bytes());
+    let buyer = Address::generate(&env);
+    stable_token.mint(&buyer, &100_0000000);
+
+    let signature = sign_quote(&env, &contract, &key, &quote);
+    quote.premium = 1;
+    contract.buy_option_with_quote(&buyer, &quote, &quoter, &signature);
+}
+
+fn barrier_setup<'a>(
+    env: &Env,
+) -> (
+    OptionsContractClient<'a>,
+    MockPriceFeedClient<'a>,
+    u64,
+    Address,
+) {
+    let admin = Address::generate(env);
+    let provider = Address::generate(env);
+    let buyer = Address::generate(env);
+    let contract = create_test_contract(env);
+    let stable_token = create_token_contract(env, &admin);
+    let price_feed = create_price_feed(env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(env, &admin).address,
+        &price_feed.address,
+        &String::from_str(env, "BTC/USDC Pool"),
+    );
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+    stable_token.mint(&buyer, &100_0000000);
+
+    (contract, price_feed, pool_id, buyer)
+}
+
+#[test]
+fn test_barrier_options() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (contract, price_feed, pool_id, buyer) = barrier_setup(&env);
+    let expiry = env.ledger().timestamp() + 86400;
+
+    let up_and_out_call = contract.buy_barrier_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+        &BarrierTerms {
+            level: 2400_0000000,
+            direction: BarrierDirection::Up,
+            kind: BarrierKind::KnockOut,
+        },
+    );
+    let down_and_in_put = contract.buy_barrier_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Put,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+        &BarrierTerms {
+            level: 1600_0000000,
+            direction: BarrierDirection::Down,
+            kind: BarrierKind::KnockIn,
+        },
+    );
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 4000_0000000);
+
+    // A put that only knocks in after a 20% drop within a day is priced near zero
+    assert!(contract.get_option(&down_and_in_put).premium_paid < 1_0000000);
+
+    // A print above 2400 knocks the call out and frees its collateral, even after
+    // the price falls back
+    env.ledger().with_mut(|l| l.timestamp += 300);
+    price_feed.set_price(&2500_0000000);
+    env.ledger().with_mut(|l| l.timestamp += 300);
+    price_feed.set_price(&2300_0000000);
+    contract.trigger_barrier(&up_and_out_call);
+
+    let call = contract.get_option(&up_and_out_call);
+    assert!(!call.is_active);
+    assert!(call.barrier.get().unwrap().is_crossed);
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 2000_0000000);
+
+    // A print below 1600 knocks the put in; it pays on the price at exercise
+    env.ledger().with_mut(|l| l.timestamp += 300);
+    price_feed.set_price(&1500_0000000);
+    contract.trigger_barrier(&down_and_in_put);
+    assert!(
+        contract
+            .get_option(&down_and_in_put)
+            .barrier
+            .get()
+            .unwrap()
+            .is_crossed
+    );
+
+    env.ledger().with_mut(|l| l.timestamp += 300);
+    price_feed.set_price(&1700_0000000);
+    assert_eq!(contract.exercise_option(&down_and_in_put), 300_0000000);
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 0);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #35)")]
+fn test_knock_in_not_exercisable_before_barrier() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (contract, price_feed, pool_id, buyer) = barrier_setup(&env);
+
+    let option_id = contract.buy_barrier_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Put,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &1_0000000,
+        &BarrierTerms {
+            level: 1600_0000000,
+            direction: BarrierDirection::Down,
+            kind: BarrierKind::KnockIn,
+        },
+    );
+
+    env.ledger().with_mut(|l| l.timestamp += 300);
+    price_feed.set_price(&1700_0000000);
+    contract.exercise_option(&option_id);
+}
+
+#[test]
+fn test_barrier_paged_monitoring() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (contract, price_feed, pool_id, buyer) = barrier_setup(&env);
+    let start = env.ledger().timestamp();
+    let option_id = contract.buy_barrier_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &(start + 3 * 86400),
+        &1_0000000,
+        &BarrierTerms {
+            level: 2400_0000000,
+            direction: BarrierDirection::Up,
+            kind: BarrierKind::KnockOut,
+        },
+    );
+
+    // The crossing is more than one page of history after purchase
+    for period in 1..=250 {
+        env.ledger()
+            .with_mut(|l| l.timestamp = start + period * 300);
+        price_feed.set_price(&2100_0000000);
+    }
+    env.ledger().with_mut(|l| l.timestamp = start + 251 * 300);
+    price_feed.set_price(&2500_0000000);
+
+    assert!(!contract.trigger_barrier(&option_id));
+    let barrier = contract.get_option(&option_id).barrier.get().unwrap();
+    assert_eq!(barrier.monitor_from, start + 200 * 300);
+    assert!(contract.get_option(&option_id).is_active);
+
+    assert!(contract.trigger_barrier(&option_id));
+    assert!(!contract.get_option(&option_id).is_active);
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 0);
+}
+
+#[test]
+fn test_barrier_pricing() {
+    let env = Env::default();
+    let years = pricing::years_until(&env, 0, 30 * 86400);
+    let vol = 8_000_000;
+    let variance = vol * vol / SCALE * years / SCALE;
+    let barrier = |is_call: bool, level: i128, is_up: bool, knock_in: bool| {
+        pricing::barrier_price(
+            &env,
+            2000 * SCALE,
+            2000 * SCALE,
+            level * SCALE,
+            vol,
+            years,
+            is_call,
+            is_up,
+            knock_in,
+        )
+    };
+
+    // Up-and-out call knocked out at 2400 is worth a fraction of the vanilla
+    let vanilla_call = pricing::black_price(&env, 2000 * SCALE, 2000 * SCALE, variance, true);
+    assert!((barrier(true, 2400, true, false) - 16_4031528).abs() < 100_000);
+    assert_eq!(
+        barrier(true, 2400, true, false) + barrier(true, 2400, true, true),
+        vanilla_call
+    );
+
+    // Down-and-in put at 1600
+    assert!((barrier(false, 1600, false, true) - 151_8178370).abs() < 100_000);
+
+    // Already through the barrier: knocked out, or worth the vanilla once knocked in
+    assert_eq!(barrier(true, 1900, true, false), 0);
+    assert_eq!(barrier(true, 1900, true, true), vanilla_call);
+}
+
+#[test]
+fn test_pricing_math_vectors() {
+    let env = Env::default();
+
+    let close = |actual: i128, expected: i128, tolerance: i128| {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} not within {tolerance} of {expected}"
+        );
+    };
+
+    close(fixed_point::ln(&env, SCALE), 0, 1);
+    close(fixed_point::ln(&env, 2 * SCALE), 6_931_472, 2);
+    close(fixed_point::ln(&env, SCALE / 2), -6_931_472, 2);
+    close(fixed_point::ln(&env, 10 * SCALE), 23_025_851, 2);
+    close(fixed_point::exp(&env, 0), SCALE, 1);
+    close(fixed_point::exp(&env, SCALE), 27_182_818, 2);
+    close(fixed_point::exp(&env, -SCALE), 3_678_794, 2);
+    assert_eq!(fixed_point::exp(&env, -50 * SCALE), 0);
+    assert_eq!(fixed_point::sqrt(&env, 4 * SCALE), 2 * SCALE);
+    assert_eq!(fixed_point::sqrt(&env, 2 * SCALE), 14_142_135);
+
+    close(pricing::norm_cdf(&env, 0), 5_000_000, 2);
+    close(pricing::norm_cdf(&env, SCALE), 8_413_447, 2);
+    close(pricing::norm_cdf(&env, -19_600_000), 249_979, 2);
+    assert_eq!(pricing::norm_cdf(&env, 9 * SCALE), SCALE);
+
+    // At the money with 80% vol over a year: d2 = -0.4
+    let year = pricing::SECONDS_PER_YEAR as u64;
+    let years = pricing::years_until(&env, 0, year);
+    let call = pricing::digital_price(&env, 2000_0000000, 2000_0000000, 8_000_000, years, true);
+    let put = pricing::digital_price(&env, 2000_0000000, 2000_0000000, 8_000_000, years, false);
+    close(call, 3_445_783, 3);
+    close(call + put, SCALE, 1);
+
+    // Expired digitals are worth their intrinsic outcome
+    assert_eq!(
+        pricing::digital_price(&env, 2100_0000000, 2000_0000000, 8_000_000, 0, true),
+        SCALE
+    );
+    assert_eq!(
+        pricing::digital_price(&env, 2100_0000000, 2000_0000000, 8_000_000, 0, false),
+        0
+    );
+}
+
+#[test]
+fn test_digital_options() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+    stable_token.mint(&buyer, &500_0000000);
+
+    // Two units paying 100 each lock exactly 200 of pool liquidity
+    let expiry = env.ledger().timestamp() + pricing::SECONDS_PER_YEAR as u64;
+    let call_id = contract.buy_digital_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &2_0000000,
+        &100_0000000,
+    );
+    let put_id = contract.buy_digital_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Put,
+        &2000_0000000,
+        &expiry,
+        &2_0000000,
+        &100_0000000,
+    );
+
+    let call = contract.get_option(&call_id);
+    assert_eq!(call.payoff, OptionPayoff::CashOrNothing(100_0000000));
+    assert_eq!(call.collateral, 200_0000000);
+    assert!((call.premium_paid - 68_9156600).abs() < 1000);
+    let put = contract.get_option(&put_id);
+    assert!((call.premium_paid + put.premium_paid - 200_0000000).abs() <= 2);
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 400_0000000);
+
+    // Settles on the last print at or before expiry, not the later one
+    env.ledger().with_mut(|l| l.timestamp = expiry - 100);
+    price_feed.set_price(&2100_0000000);
+    env.ledger().with_mut(|l| l.timestamp = expiry + 10);
+    price_feed.set_price(&1900_0000000);
+
+    // and is still found after more prints than one prices() call returns
+    for period in 1..=240 {
+        env.ledger()
+            .with_mut(|l| l.timestamp = expiry + period * 300);
+        price_feed.set_price(&1900_0000000);
+    }
+
+    let balance = stable_token.balance(&buyer);
+    assert_eq!(contract.exercise_option(&call_id), 200_0000000);
+    assert_eq!(contract.exercise_option(&put_id), 0);
+    assert_eq!(stable_token.balance(&buyer), balance + 200_0000000);
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 0);
+}
+
+#[test]
+fn test_asian_pricing() {
+    let env = Env::default();
+    let year = SCALE;
+
+    // Black-76 at the money with 20% vol over a year: S * (N(0.1) - N(-0.1))
+    let vanilla = pricing::black_price(&env, 100 * SCALE, 100 * SCALE, 400_000, true);
+    assert!((vanilla - 7_9655700).abs() < 20_000);
+
+    // Averaging over the whole year roughly divides the variance by three
+    let asian = pricing::asian_price(&env, 100 * SCALE, 100 * SCALE, 2_000_000, year, year, true);
+    assert!((asian - 4_6117000).abs() < 20_000);
+
+    // A vanishing window converges to the vanilla price
+    let short_window =
+        pricing::asian_price(&env, 100 * SCALE, 100 * SCALE, 2_000_000, year, 1, true);
+    assert!((short_window - vanilla).abs() < 1_000);
+
+    // Put-call parity holds for the average with zero rates
+    let asian_put =
+        pricing::asian_price(&env, 100 * SCALE, 90 * SCALE, 2_000_000, year, year, false);
+    let asian_call =
+        pricing::asian_price(&env, 100 * SCALE, 90 * SCALE, 2_000_000, year, year, true);
+    assert!((asian_call - asian_put - 10 * SCALE).abs() < 100);
+}
+
+#[test]
+fn test_asian_option_settles_on_average() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+    stable_token.mint(&buyer, &500_0000000);
+
+    let hour = 3600u64;
+    let expiry = env.ledger().timestamp() + 7 * 86400;
+    let averaging = AveragingParams {
+        window: 12 * hour,
+        min_samples: 3,
+    };
+    let option_id = contract.buy_asian_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+        &averaging,
+    );
+    let option = contract.get_option(&option_id);
+    assert_eq!(option.payoff, OptionPayoff::Average(averaging));
+    assert_eq!(option.style, OptionStyle::European);
+    assert!(option.premium_paid > 0 && option.premium_paid < 100_0000000);
+
+    // Only prints inside the final twelve hours count: (2100 + 2400 + 2100) / 3 = 2200
+    for (time, price) in [
+        (expiry - 13 * hour, 3000_0000000),
+        (expiry - 8 * hour, 2100_0000000),
+        (expiry - 4 * hour, 2400_0000000),
+        (expiry, 2100_0000000),
+    ] {
+        env.ledger().with_mut(|l| l.timestamp = time);
+        price_feed.set_price(&price);
+    }
+
+    // Late in the exercise window the window is still read back in full
+    for period in 1..=240 {
+        env.ledger()
+            .with_mut(|l| l.timestamp = expiry + period * 300);
+        price_feed.set_price(&3000_0000000);
+    }
+    assert_eq!(contract.exercise_option(&option_id), 200_0000000);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #37)")]
+fn test_asian_window_longer_than_history() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &create_token_contract(&env, &admin).address,
+        &create_token_contract(&env, &admin).address,
+        &create_price_feed(&env, 2000_0000000).address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    // 200 periods of 300s can't be read back in one call
+    contract.buy_asian_option(
+        &pool_id,
+        &Address::generate(&env),
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 7 * 86400),
+        &1_0000000,
+        &AveragingParams {
+            window: 200 * 300,
+            min_samples: 1,
+        },
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #37)")]
+fn test_asian_window_longer_than_expiry() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &create_token_contract(&env, &admin).address,
+        &create_token_contract(&env, &admin).address,
+        &create_price_feed(&env, 2000_0000000).address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+
+    contract.buy_asian_option(
+        &pool_id,
+        &Address::generate(&env),
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &1_0000000,
+        &AveragingParams {
+            window: 2 * 86400,
+            min_samples: 1,
+        },
+    );
+}
+
+fn perpetual_setup<'a>(
+    env: &Env,
+) -> (
+    OptionsContractClient<'a>,
+    TestToken,
+    MockPriceFeedClient<'a>,
+    u64,
+    Address,
+) {
+    let admin = Address::generate(env);
+    let buyer = Address::generate(env);
+    let provider = Address::generate(env);
+    let contract = create_test_contract(env);
+
+    let stable_token = create_token_contract(env, &admin);
+    let price_feed = create_price_feed(env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(env, &admin).address,
+        &price_feed.address,
+        &String::from_str(env, "BTC/USDC Pool"),
+    );
+    stable_token.mint(&provider, &10_000_0000000);
+    contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+    stable_token.mint(&buyer, &500_0000000);
+
+    (contract, stable_token, price_feed, pool_id, buyer)
+}
+
+#[test]
+fn test_perpetual_options() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, price_feed, pool_id, buyer) = perpetual_setup(&env);
+
+    let option_id = contract.buy_perpetual_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &1_0000000,
+        &100_0000000,
+    );
+    let option = contract.get_option(&option_id);
+    assert_eq!(option.expiry, u64::MAX);
+    assert_eq!(option.premium_paid, 0);
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 2000_0000000);
+    assert_eq!(stable_token.balance(&buyer), 400_0000000);
+
+    // A day at the money costs the time value of a one-day option: 2000 * (2N(0.0209) - 1)
+    env.ledger().with_mut(|l| l.timestamp += 86400);
+    let funding = contract.collect_funding(&option_id);
+    assert!((funding.total_paid - 33_4098000).abs() < 10_000);
+    assert_eq!(funding.deposit, 100_0000000 - funding.total_paid);
+    assert_eq!(
+        contract.get_pool_total_liquidity(&pool_id),
+        10_000_0000000 + funding.total_paid
+    );
+
+    // Exercisable any time; the unused deposit comes back with the payoff
+    price_feed.set_price(&2100_0000000);
+    assert_eq!(contract.exercise_option(&option_id), 100_0000000);
+    assert_eq!(
+        stable_token.balance(&buyer),
+        400_0000000 + 100_0000000 + funding.deposit
+    );
+    assert_eq!(contract.get_perpetual_funding(&option_id).deposit, 0);
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 0);
+}
+
+#[test]
+fn test_perpetual_liquidation() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, price_feed, pool_id, buyer) = perpetual_setup(&env);
+
+    let option_id = contract.buy_perpetual_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Put,
+        &2000_0000000,
+        &1_0000000,
+        &40_0000000,
+    );
+
+    // Three days of funding outrun the deposit; the rest is carried as unpaid
+    env.ledger().with_mut(|l| l.timestamp += 3 * 86400);
+    let funding = contract.collect_funding(&option_id);
+    assert_eq!(funding.deposit, 0);
+    assert_eq!(funding.total_paid, 40_0000000);
+    assert!(funding.unpaid > 60_0000000);
+
+    // The keeper closes it at spot and the pool withholds the unpaid funding
+    price_feed.set_price(&1800_0000000);
+    let balance = stable_token.balance(&buyer);
+    let payout = contract.liquidate_perpetual(&option_id);
+    assert_eq!(payout, 200_0000000 - funding.unpaid);
+    assert_eq!(stable_token.balance(&buyer), balance + payout);
+
+    let option = contract.get_option(&option_id);
+    assert!(!option.is_active);
+    assert_eq!(
+        contract.get_perpetual_funding(&option_id).total_paid,
+        40_0000000 + funding.unpaid
+    );
+    assert_eq!(contract.get_pool_locked_collateral(&pool_id), 0);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #39)")]
+fn test_liquidate_funded_perpetual() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, _, pool_id, buyer) = perpetual_setup(&env);
+
+    let option_id = contract.buy_perpetual_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &1_0000000,
+        &100_0000000,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp += 86400);
+    contract.liquidate_perpetual(&option_id);
+}
+
+#[test]
+fn test_vol_surface() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, _, pool_id, buyer) = perpetual_setup(&env);
+    let oracle = Address::generate(&env);
+
+    // Without a surface every strike and expiry gets the flat pool volatility
+    let day = 86400u64;
+    let now = env.ledger().timestamp();
+    assert_eq!(
+        contract.get_implied_vol(&pool_id, &1800_0000000, &(now + 30 * day)),
+        DEFAULT_VOLATILITY
+    );
+
+    contract.set_vol_oracle(&pool_id, &oracle);
+    assert_eq!(contract.get_vol_oracle(&pool_id), Some(oracle));
+
+    env.ledger().with_mut(|l| l.timestamp += 100);
+    let now = env.ledger().timestamp();
+    contract.update_vol_surface(
+        &pool_id,
+        &vec![&env, 9_000_000, 10_000_000, 11_000_000],
+        &vec![&env, 30 * day, 90 * day],
+        &vec![
+            &env, 7_000_000, 6_000_000, 6_500_000, // 30 days
+            6_000_000, 5_000_000, 5_500_000, // 90 days
+        ],
+    );
+    assert_eq!(contract.get_vol_surface(&pool_id).updated_at, now);
+
+    // Grid points, bilinear midpoints and flat extrapolation (spot is 2000)
+    let vol =
+        |strike: i128, days: u64| contract.get_implied_vol(&pool_id, &strike, &(now + days * day));
+    assert_eq!(vol(2000_0000000, 30), 6_000_000);
+    assert_eq!(vol(1800_0000000, 90), 6_000_000);
+    assert_eq!(vol(1900_0000000, 30), 6_500_000);
+    assert_eq!(vol(2000_0000000, 60), 5_500_000);
+    assert_eq!(vol(2100_0000000, 60), 5_750_000);
+    assert_eq!(vol(1000_0000000, 1), 7_000_000);
+    assert_eq!(vol(4000_0000000, 365), 5_500_000);
+
+    // The premium engine prices off the interpolated vol
+    let expiry = now + 60 * day;
+    let option_id = contract.buy_digital_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2100_0000000,
+        &expiry,
+        &1_0000000,
+        &100_0000000,
+    );
+    let probability = pricing::digital_price(
+        &env,
+        2000_0000000,
+        2100_0000000,
+        5_750_000,
+        pricing::years_until(&env, now, expiry),
+        true,
+    );
+    assert_eq!(
+        contract.get_option(&option_id).premium_paid,
+        mul_div_ceil(&env, 100_0000000, probability, SCALE)
+    );
+
+    // Vanilla options are priced with Black-76 instead of the flat premium rate
+    let option_id = contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Put,
+        &2100_0000000,
+        &expiry,
+        &1_0000000,
+    );
+    let years = pricing::years_until(&env, now, expiry);
+    let variance = mul_div_floor(
+        &env,
+        mul_div_floor(&env, 5_750_000, 5_750_000, SCALE),
+        years,
+        SCALE,
+    );
+    assert_eq!(
+        contract.get_option(&option_id).premium_paid,
+        pricing::black_price(&env, 2000_0000000, 2100_0000000, variance, false)
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #40)")]
+fn test_vol_surface_out_of_bounds() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, _, pool_id, _) = perpetual_setup(&env);
+
+    contract.set_vol_oracle(&pool_id, &Address::generate(&env));
+    contract.update_vol_surface(
+        &pool_id,
+        &vec![&env, 10_000_000],
+        &vec![&env, 86400],
+        &vec![&env, 60_000_000],
+    );
+}
+
+#[test]
+fn test_option_greeks_and_pool_risk() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, price_feed, pool_id, buyer) = perpetual_setup(&env);
+
+    let expiry = env.ledger().timestamp() + pricing::SECONDS_PER_YEAR as u64;
+    let call_id = contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &2_0000000,
+    );
+    let put_id = contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Put,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+    );
+    assert_eq!(
+        contract.get_pool_open_options(&pool_id),
+        vec![&env, call_id, put_id]
+    );
+
+    // At the money, one year, 80% vol: d1 = 0.4
+    let call = contract.get_option_greeks(&call_id);
+    assert!((call.price - 2 * 621_6868000).abs() < 1_0000000); // 2 * S * (2N(0.4) - 1)
+    assert!((call.delta - 2 * 6_554217).abs() < 20_000); // 2 * N(0.4)
+    assert!((call.gamma - 2 * 2302).abs() < 20); // 2 * pdf(0.4) / (S * vol)
+    assert!((call.vega - 2 * 7_3654000).abs() < 500_000); // 2 * S * pdf(0.4) / 100
+    assert!((call.theta + 2 * 8072000).abs() < 100_000); // 2 * S * pdf(0.4) * vol / 2 / 365
+
+    let put = contract.get_option_greeks(&put_id);
+    assert!((put.delta + 3_445783).abs() < 20_000);
+    assert_eq!(put.gamma * 2, call.gamma);
+
+    // The pool is short both options
+    let risk = contract.get_pool_risk(&pool_id, &2_000);
+    assert_eq!(risk.open_options, 2);
+    assert_eq!(risk.net_delta, -(call.delta + put.delta));
+    assert_eq!(risk.total_vega, -(call.vega + put.vega));
+    assert_eq!(risk.shock_bps, 2_000);
+    assert_eq!(risk.payout_up, 800_0000000);
+    assert_eq!(risk.payout_down, 400_0000000);
+
+    // Exercised options drop out of the pool's book
+    price_feed.set_price(&2100_0000000);
+    contract.exercise_option(&call_id);
+    assert_eq!(contract.get_pool_open_options(&pool_id), vec![&env, put_id]);
+    let risk = contract.get_pool_risk(&pool_id, &2_000);
+    assert_eq!(risk.open_options, 1);
+    assert_eq!(risk.payout_up, 0);
+    assert_eq!(risk.payout_down, 320_0000000);
+
+    // A harsher scenario can be asked for
+    let risk = contract.get_pool_risk(&pool_id, &5_000);
+    assert_eq!(risk.shock_bps, 5_000);
+    assert_eq!(risk.payout_down, 950_0000000);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #4)")]
+fn test_pool_risk_shock_out_of_bounds() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, _, pool_id, _) = perpetual_setup(&env);
+
+    contract.get_pool_risk(&pool_id, &10_000);
+}
+
+#[test]
+fn test_pool_mark_to_market() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, price_feed, pool_id, buyer) = perpetual_setup(&env);
+
+    // Without open options NAV is just the booked liquidity
+    let mtm = contract.get_pool_mark_to_market(&pool_id);
+    assert_eq!(mtm.nav, 10_000_0000000);
+    assert_eq!(mtm.option_liability, 0);
+
+    let expiry = env.ledger().timestamp() + pricing::SECONDS_PER_YEAR as u64;
+    contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+    );
+
+    // The 40 premium is booked, but the option is worth ~621.69 to its holder
+    let mtm = contract.get_pool_mark_to_market(&pool_id);
+    assert_eq!(mtm.total_liquidity, 10_040_0000000);
+    assert!((mtm.option_liability - 621_6868000).abs() < 1_0000000);
+    assert_eq!(mtm.nav, mtm.total_liquidity - mtm.option_liability);
+    assert_eq!(mtm.open_premiums, 40_0000000);
+    assert_eq!(mtm.unrealized_pnl, 40_0000000 - mtm.option_liability);
+    assert_eq!(mtm.open_options, 1);
+
+    // New LPs buy in at NAV rather than the booked liquidity
+    let lp = Address::generate(&env);
+    stable_token.mint(&lp, &1_000_0000000);
+    let total_shares = contract.get_pool_total_lp_shares(&pool_id);
+    let shares = contract.provide_liquidity(&pool_id, &lp, &1_000_0000000, &0);
+    assert_eq!(shares, 1_000_0000000 * total_shares / mtm.nav);
+
+    // A rally raises the liability, and shares redeem at the lower NAV
+    price_feed.set_price(&3000_0000000);
+    let mtm = contract.get_pool_mark_to_market(&pool_id);
+    assert!(mtm.option_liability > 1_000_0000000);
+    let total_shares = contract.get_pool_total_lp_shares(&pool_id);
+    let withdrawn = contract.withdraw_liquidity(&pool_id, &lp, &shares);
+    assert_eq!(withdrawn, shares * mtm.nav / total_shares);
+    assert!(withdrawn < 1_000_0000000);
+    assert_eq!(stable_token.balance(&lp), withdrawn);
+}
+
+#[test]
+fn test_pool_mark_to_market_includes_strategies() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, _, pool_id, buyer) = perpetual_setup(&env);
+
+    // The pool is short a 1,900/2,100 call spread to the owner
+    let expiry = env.ledger().timestamp() + 7 * 86400;
+    let leg = |side: PositionSide, strike: i128| StrategyLeg {
+        opt_type: OptionType::Call,
+        side,
+        strike,
+        expiry,
+        amount: 1_0000000,
+    };
+    let strategy_id = contract.buy_strategy(
+        &pool_id,
+        &buyer,
+        &vec![
+            &env,
+            leg(PositionSide::Long, 1900_0000000),
+            leg(PositionSide::Short, 2100_0000000),
+        ],
+    );
+    assert_eq!(
+        contract.get_pool_open_strategies(&pool_id),
+        vec![&env, strategy_id]
+    );
+
+    // The spread is worth ~96.01 to the owner (the pool locked its 200 width)
+    let mtm = contract.get_pool_mark_to_market(&pool_id);
+    assert_eq!(mtm.open_options, 0);
+    assert_eq!(mtm.open_strategies, 1);
+    assert!((mtm.option_liability - 96_0105200).abs() < 1_0000000);
+    assert_eq!(mtm.nav, mtm.total_liquidity - mtm.option_liability);
+    assert_eq!(mtm.open_premiums, -4_0000000);
+    let page = contract.get_pool_mark_to_market_page(&pool_id, &0, &1);
+    assert_eq!(page.open_strategies, 1);
+    assert_eq!(page.option_liability, mtm.option_liability);
+
+    let risk = contract.get_pool_risk(&pool_id, &2_000);
+    assert_eq!(risk.open_strategies, 1);
+    assert!(risk.net_delta < 0);
+    assert_eq!(risk.payout_up, 200_0000000);
+    assert_eq!(risk.payout_down, 0);
+
+    contract.close_strategy(&strategy_id);
+    let mtm = contract.get_pool_mark_to_market(&pool_id);
+    assert_eq!(mtm.open_strategies, 0);
+    assert_eq!(mtm.option_liability, 0);
+}
+
+fn queue_new_params(
+    env: &Env,
+    contract: &OptionsContractClient,
+    pool_id: u64,
+    price_feed: &Address,
+) -> PoolParams {
+    let params = PoolParams {
+        price_feed: price_feed.clone(),
+        volatility: 6_000_000,
+        premium_bps: 300,
+        fee_bps: 500,
+        max_utilization_bps: 5_000,
+        min_expiry: 3600,
+        max_expiry: 30 * 86400,
+    };
+    let eta = contract.queue_pool_params(&pool_id, &params);
+    assert_eq!(eta, env.ledger().timestamp() + 2 * 86400);
+    params
+}
+
+#[test]
+fn test_pool_params_timelock() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, _, pool_id, buyer) = perpetual_setup(&env);
+
+    let defaults = contract.get_pool_params(&pool_id);
+    assert_eq!(defaults.volatility, DEFAULT_VOLATILITY);
+    assert_eq!(defaults.premium_bps, 200);
+    assert_eq!(defaults.fee_bps, 0);
+
+    // Nothing changes until the queued update is executed after the delay
+    let new_feed = create_price_feed(&env, 2500_0000000);
+    let params = queue_new_params(&env, &contract, pool_id, &new_feed.address);
+    assert_eq!(contract.get_pending_pool_params(&pool_id).params, params);
+    assert_eq!(contract.get_pool_params(&pool_id), defaults);
+
+    env.ledger().with_mut(|l| l.timestamp += 2 * 86400);
+    assert_eq!(contract.execute_pool_params(&pool_id), params);
+    assert_eq!(contract.get_pool_params(&pool_id), params);
+    assert_eq!(contract.get_pool(&pool_id).price_feed, new_feed.address);
+    assert_eq!(contract.get_pool_volatility(&pool_id), 6_000_000);
+
+    // 3% premium to the pool plus a 5% fee on it, a quarter of which goes to the
+    // insurance fund and the rest to the protocol
+    let expiry = env.ledger().timestamp() + 7 * 86400;
+    contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+    );
+    assert_eq!(stable_token.balance(&buyer), 500_0000000 - 63_0000000);
+    assert_eq!(contract.get_pool_total_liquidity(&pool_id), 10_060_0000000);
+    assert_eq!(contract.get_protocol_fees(&stable_token.address), 2_2500000);
+    assert_eq!(
+        contract.get_insurance_fund(&stable_token.address).balance,
+        7500000
+    );
+
+    let treasury = Address::generate(&env);
+    contract.withdraw_protocol_fees(&stable_token.address, &treasury, &2_2500000);
+    assert_eq!(stable_token.balance(&treasury), 2_2500000);
+    assert_eq!(contract.get_protocol_fees(&stable_token.address), 0);
+
+    // A queued change can be withdrawn before it takes effect
+    queue_new_params(&env, &contract, pool_id, &new_feed.address);
+    contract.cancel_pool_params(&pool_id);
+    assert_eq!(contract.get_pool_params(&pool_id), params);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #45)")]
+fn test_execute_pool_params_before_timelock() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, price_feed, pool_id, _) = perpetual_setup(&env);
+
+    queue_new_params(&env, &contract, pool_id, &price_feed.address);
+    env.ledger().with_mut(|l| l.timestamp += 86400);
+    contract.execute_pool_params(&pool_id);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #46)")]
+fn test_option_expiry_out_of_bounds() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, price_feed, pool_id, buyer) = perpetual_setup(&env);
+
+    queue_new_params(&env, &contract, pool_id, &price_feed.address);
+    env.ledger().with_mut(|l| l.timestamp += 2 * 86400);
+    contract.execute_pool_params(&pool_id);
+
+    contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 60 * 86400),
+        &1_0000000,
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #46)")]
+fn test_asian_option_expiry_out_of_bounds() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, price_feed, pool_id, buyer) = perpetual_setup(&env);
+
+    queue_new_params(&env, &contract, pool_id, &price_feed.address);
+    env.ledger().with_mut(|l| l.timestamp += 2 * 86400);
+    contract.execute_pool_params(&pool_id);
+
+    contract.buy_asian_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 60 * 86400),
+        &1_0000000,
+        &AveragingParams {
+            window: 3600,
+            min_samples: 1,
+        },
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #5)")]
+fn test_option_above_utilization_cap() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, price_feed, pool_id, buyer) = perpetual_setup(&env);
+
+    queue_new_params(&env, &contract, pool_id, &price_feed.address);
+    env.ledger().with_mut(|l| l.timestamp += 2 * 86400);
+    contract.execute_pool_params(&pool_id);
+
+    // 6_000 of collateral is within free liquidity but over the 50% cap
+    stable_token.mint(&buyer, &1_000_0000000);
+    contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 7 * 86400),
+        &3_0000000,
+    );
+}
+
+fn governance_setup<'a>(
+    env: &Env,
+) -> (OptionsContractClient<'a>, u64, Address, Address, PoolParams) {
+    let admin = Address::generate(env);
+    let alice = Address::generate(env);
+    let bob = Address::generate(env);
+    let contract = create_test_contract(env);
+
+    let stable_token = create_token_contract(env, &admin);
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(env, &admin).address,
+        &create_price_feed(env, 2000_0000000).address,
+        &String::from_str(env, "BTC/USDC Pool"),
+    );
+    stable_token.mint(&alice, &6_000_0000000);
+    stable_token.mint(&bob, &4_000_0000000);
+    contract.provide_liquidity(&pool_id, &alice, &6_000_0000000, &0);
+    contract.provide_liquidity(&pool_id, &bob, &4_000_0000000, &0);
+
+    let mut params = contract.get_pool_params(&pool_id);
+    params.premium_bps = 350;
+    params.max_utilization_bps = 8_000;
+
+    env.ledger().with_mut(|l| l.sequence_number += 10);
+    (contract, pool_id, alice, bob, params)
+}
+
+#[test]
+fn test_lp_governance() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, pool_id, alice, bob, params) = governance_setup(&env);
+    let alice_shares = contract.get_pool_lp_shares(&pool_id, &alice);
+    let bob_shares = contract.get_pool_lp_shares(&pool_id, &bob);
+
+    let proposal_id = contract.propose_pool_params(&pool_id, &alice, &params);
+    let proposal = contract.get_proposal(&proposal_id);
+    // Quorum is measured against LP-held shares, without the first deposit's dead shares
+    assert_eq!(proposal.total_shares, alice_shares + bob_shares);
+    assert_eq!(proposal.total_shares, 10_000_0000000 - 1000);
+    assert_eq!(proposal.eta, proposal.voting_ends + 2 * 86400);
+
+    // Bob exits after the snapshot but keeps the weight he had when it was taken
+    contract.withdraw_liquidity(&pool_id, &bob, &bob_shares);
+    let snapshot = proposal.snapshot_ledger;
+    assert_eq!(
+        contract.get_lp_shares_at(&pool_id, &bob, &snapshot),
+        bob_shares
+    );
+    assert_eq!(contract.cast_vote(&proposal_id, &bob, &false), bob_shares);
+    assert_eq!(
+        contract.cast_vote(&proposal_id, &alice, &true),
+        alice_shares
+    );
+    assert_eq!(contract.get_vote(&proposal_id, &alice), alice_shares);
+
+    // Shares bought at or after the proposal ledger carry no weight
+    let carol = Address::generate(&env);
+    assert_eq!(contract.get_lp_shares_at(&pool_id, &carol, &snapshot), 0);
+
+    let proposal = contract.get_proposal(&proposal_id);
+    assert_eq!(proposal.votes_for, alice_shares);
+    assert_eq!(proposal.votes_against, bob_shares);
+
+    env.ledger().with_mut(|l| l.timestamp = proposal.eta);
+    assert_eq!(contract.execute_proposal(&proposal_id), params);
+    assert_eq!(contract.get_pool_params(&pool_id), params);
+    assert!(contract.get_proposal(&proposal_id).executed);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #50)")]
+fn test_defeated_proposal_not_executable() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, pool_id, alice, bob, params) = governance_setup(&env);
+
+    let proposal_id = contract.propose_pool_params(&pool_id, &bob, &params);
+    contract.cast_vote(&proposal_id, &bob, &true);
+    contract.cast_vote(&proposal_id, &alice, &false);
+
+    let eta = contract.get_proposal(&proposal_id).eta;
+    env.ledger().with_mut(|l| l.timestamp = eta);
+    contract.execute_proposal(&proposal_id);
+}
+
+#[test]
+fn test_queued_shares_excluded_from_quorum() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, pool_id, alice, bob, params) = governance_setup(&env);
+    let alice_shares = contract.get_pool_lp_shares(&pool_id, &alice);
+    let bob_shares = contract.get_pool_lp_shares(&pool_id, &bob);
+
+    // Escrowed shares can't vote, so they don't raise the bar for those that can
+    contract.request_withdrawal(&pool_id, &bob, &bob_shares);
+    let proposal_id = contract.propose_pool_params(&pool_id, &alice, &params);
+    assert_eq!(
+        contract.get_proposal(&proposal_id).total_shares,
+        alice_shares
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #50)")]
+fn test_expired_proposal_not_executable() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, pool_id, alice, _, params) = governance_setup(&env);
+
+    let proposal_id = contract.propose_pool_params(&pool_id, &alice, &params);
+    contract.cast_vote(&proposal_id, &alice, &true);
+
+    let proposal = contract.get_proposal(&proposal_id);
+    assert_eq!(proposal.expires_at, proposal.eta + 3 * 86400);
+    env.ledger()
+        .with_mut(|l| l.timestamp = proposal.expires_at + 1);
+    contract.execute_proposal(&proposal_id);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #48)")]
+fn test_vote_after_voting_period() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, pool_id, alice, bob, params) = governance_setup(&env);
+
+    let proposal_id = contract.propose_pool_params(&pool_id, &alice, &params);
+    let voting_ends = contract.get_proposal(&proposal_id).voting_ends;
+    env.ledger().with_mut(|l| l.timestamp = voting_ends);
+    contract.cast_vote(&proposal_id, &bob, &true);
+}
+
+#[test]
+fn test_pool_wind_down_and_retirement() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    let underlying_token = create_token_contract(&env, &admin);
+    let price_feed = create_price_feed(&env, 1000_0000000);
+
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &underlying_token.address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+    stable_token.mint(&provider, &10_000_0000000);
+    stable_token.mint(&buyer, &100_0000000);
+    let shares = contract.provide_liquidity(&pool_id, &provider, &10_000_0000000, &0);
+
+    let expiry = env.ledger().timestamp() + 86400;
+    let option_id = contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+    );
+
+    // Free liquidity can leave while the open option runs off
+    contract.wind_down_pool(&pool_id);
+    let pool = contract.get_pool(&pool_id);
+    assert!(pool.winding_down && !pool.is_active);
+    contract.withdraw_liquidity(&pool_id, &provider, &(shares / 2));
+
+    env.ledger().with_mut(|l| l.timestamp = expiry + 1);
+    contract.expire_option(&option_id);
+    contract.withdraw_liquidity(&pool_id, &provider, &(shares - shares / 2));
+    assert_eq!(contract.get_pool_total_lp_shares(&pool_id), DEAD_SHARES);
+    assert_eq!(
+        stable_token.balance(&provider) + contract.get_pool_total_liquidity(&pool_id),
+        10_040_0000000
+    );
+
+    // A retired pool drops out of its pair's pools
+    contract.retire_pool(&pool_id);
+    assert!(contract.get_pool(&pool_id).is_retired);
+    let new_pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &underlying_token.address,
+        &price_feed.address,
+        &String::from_str(&env, "BTC/USDC Pool v2"),
+    );
+    assert_eq!(
+        contract.get_pool_by_assets(&stable_token.address, &underlying_token.address),
+        vec![&env, new_pool_id]
+    );
+}
+
+#[test]
+fn test_withdrawal_queue_during_wind_down() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, _, pool_id, buyer) = perpetual_setup(&env);
+
+    let provider = Address::generate(&env);
+    stable_token.mint(&provider, &1_000_0000000);
+    let shares = contract.provide_liquidity(&pool_id, &provider, &1_000_0000000, &0);
+    contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Put,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &1_0000000,
+    );
+
+    // Queued shares are filled from the same unlocked liquidity as in an active pool
+    contract.wind_down_pool(&pool_id);
+    let nav = contract.get_pool_mark_to_market(&pool_id).nav;
+    let total_shares = contract.get_pool_total_lp_shares(&pool_id);
+    contract.request_withdrawal(&pool_id, &provider, &shares);
+    let paid = contract.process_withdrawals(&pool_id);
+    assert_eq!(paid, shares * nav / total_shares);
+    assert_eq!(stable_token.balance(&provider), paid);
+    assert_eq!(contract.get_withdrawal_queue(&pool_id).len(), 0);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #13)")]
+fn test_withdrawal_request_on_retired_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let contract = create_test_contract(&env);
+
+    let stable_token = create_token_contract(&env, &admin);
+    contract.initialize(&admin);
+    let pool_id = contract.add_liquidity_pool(
+        &stable_token.address,
+        &create_token_contract(&env, &admin).address,
+        &create_price_feed(&env, 2000_0000000).address,
+        &String::from_str(&env, "BTC/USDC Pool"),
+    );
+    stable_token.mint(&provider, &1_000_0000000);
+    let shares = contract.provide_liquidity(&pool_id, &provider, &1_000_0000000, &0);
+
+    contract.wind_down_pool(&pool_id);
+    contract.withdraw_liquidity(&pool_id, &provider, &shares);
+    contract.retire_pool(&pool_id);
+    contract.request_withdrawal(&pool_id, &provider, &1);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #51)")]
+fn test_retire_pool_with_open_options() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, _, pool_id, buyer) = perpetual_setup(&env);
+
+    contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Put,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &1_0000000,
+    );
+    contract.wind_down_pool(&pool_id);
+    contract.retire_pool(&pool_id);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #13)")]
+fn test_no_new_options_while_winding_down() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, _, pool_id, buyer) = perpetual_setup(&env);
+
+    contract.wind_down_pool(&pool_id);
+    contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Put,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &1_0000000,
+    );
+}
+
+// Two BTC/USDC pools: a conservative one with 10,000 of liquidity at the default 2%
+// premium, and a smaller aggressive one with 1,000 at 1% premium plus a 10% fee
+fn pair_setup<'a>(
+    env: &Env,
+) -> (
+    OptionsContractClient<'a>,
+    Address,
+    Address,
+    Address,
+    u64,
+    u64,
+) {
+    let admin = Address::generate(env);
+    let provider = Address::generate(env);
+    let buyer = Address::generate(env);
+    let contract = create_test_contract(env);
+
+    let stable_token = create_token_contract(env, &admin);
+    let underlying = create_token_contract(env, &admin).address;
+    let price_feed = create_price_feed(env, 2000_0000000);
+
+    contract.initialize(&admin);
+    let conservative = contract.add_liquidity_pool(
+        &stable_token.address,
+        &underlying,
+        &price_feed.address,
+        &String::from_str(env, "BTC/USDC Conservative"),
+    );
+    let aggressive = contract.add_liquidity_pool(
+        &stable_token.address,
+        &underlying,
+        &price_feed.address,
+        &String::from_str(env, "BTC/USDC Aggressive"),
+    );
+    contract.queue_pool_params(
+        &aggressive,
+        &PoolParams {
+            price_feed: price_feed.address.clone(),
+            volatility: 10_000_000,
+            premium_bps: 100,
+            fee_bps: 1000,
+            max_utilization_bps: 10_000,
+            min_expiry: 0,
+            max_expiry: 7 * 86400,
+        },
+    );
+    env.ledger().with_mut(|l| l.timestamp += 2 * 86400);
+    contract.execute_pool_params(&aggressive);
+
+    stable_token.mint(&provider, &11_000_0000000);
+    contract.provide_liquidity(&conservative, &provider, &10_000_0000000, &0);
+    contract.provide_liquidity(&aggressive, &provider, &1_000_0000000, &0);
+    stable_token.mint(&buyer, &500_0000000);
+
+    (
+        contract,
+        stable_token.address,
+        underlying,
+        buyer,
+        conservative,
+        aggressive,
+    )
+}
+
+#[test]
+fn test_route_to_cheapest_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, underlying, _, conservative, aggressive) = pair_setup(&env);
+    let expiry = env.ledger().timestamp() + 86400;
+
+    let quotes = contract.get_pair_quotes(
+        &stable_token,
+        &underlying,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+    );
+    assert_eq!(quotes.len(), 2);
+    assert_eq!(
+        quotes.get(1).unwrap(),
+        PoolQuote {
+            pool_id: aggressive,
+            premium: 20_0000000,
+            fee: 2_0000000,
+            collateral: 2000_0000000,
+            capacity: 1_000_0000000,
+        }
+    );
+
+    // The aggressive pool is cheaper but too small to write a whole unit
+    let best = contract.get_best_pool(
+        &stable_token,
+        &underlying,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+    );
+    assert_eq!(best.pool_id, conservative);
+    assert_eq!(best.premium + best.fee, 40_0000000);
+
+    let best = contract.get_best_pool(
+        &stable_token,
+        &underlying,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &2500000,
+    );
+    assert_eq!(best.pool_id, aggressive);
+
+    // Past its max expiry only the conservative pool quotes
+    let far_expiry = env.ledger().timestamp() + 30 * 86400;
+    let quotes = contract.get_pair_quotes(
+        &stable_token,
+        &underlying,
+        &OptionType::Call,
+        &2000_0000000,
+        &far_expiry,
+        &2500000,
+    );
+    assert_eq!(quotes.len(), 1);
+    assert_eq!(quotes.get(0).unwrap().pool_id, conservative);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #5)")]
+fn test_route_without_liquidity() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, underlying, _, _, _) = pair_setup(&env);
+
+    contract.get_best_pool(
+        &stable_token,
+        &underlying,
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &10_0000000,
+    );
+}
+
+#[test]
+fn test_buy_option_best() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, underlying, buyer, conservative, aggressive) = pair_setup(&env);
+    let stable = token::Client::new(&env, &stable_token);
+    let expiry = env.ledger().timestamp() + 86400;
+
+    // A quarter unit fits in the cheaper aggressive pool: 1% premium plus a 10% fee
+    let option_ids = contract.buy_option_best(
+        &stable_token,
+        &underlying,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &2500000,
+        &5_5000000,
+        &false,
+    );
+    assert_eq!(option_ids.len(), 1);
+    assert_eq!(
+        contract.get_option(&option_ids.get(0).unwrap()).pool_id,
+        aggressive
+    );
+    assert_eq!(stable.balance(&buyer), 494_5000000);
+
+    // A whole unit only fits in the conservative pool
+    let option_ids = contract.buy_option_best(
+        &stable_token,
+        &underlying,
+        &buyer,
+        &OptionType::Put,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+        &40_0000000,
+        &false,
+    );
+    let option = contract.get_option(&option_ids.get(0).unwrap());
+    assert_eq!(option.pool_id, conservative);
+    assert_eq!(option.premium_paid, 40_0000000);
+}
+
+#[test]
+fn test_buy_option_best_split() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, underlying, buyer, conservative, aggressive) = pair_setup(&env);
+    let stable = token::Client::new(&env, &stable_token);
+
+    // 10,500 of collateral fits in neither pool alone; the cheaper aggressive pool
+    // takes all it can and the conservative pool writes the rest
+    let option_ids = contract.buy_option_best(
+        &stable_token,
+        &underlying,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &5_2500000,
+        &201_0000000,
+        &true,
+    );
+    assert_eq!(option_ids.len(), 2);
+    let first = contract.get_option(&option_ids.get(0).unwrap());
+    let second = contract.get_option(&option_ids.get(1).unwrap());
+    assert_eq!((first.pool_id, first.amount), (aggressive, 5000000));
+    assert_eq!((second.pool_id, second.amount), (conservative, 4_7500000));
+    assert_eq!(
+        contract.get_pool_locked_collateral(&aggressive),
+        1_000_0000000
+    );
+    assert_eq!(stable.balance(&buyer), 299_0000000);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #5)")]
+fn test_buy_option_best_without_split() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, underlying, buyer, _, _) = pair_setup(&env);
+
+    contract.buy_option_best(
+        &stable_token,
+        &underlying,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &5_2500000,
+        &500_0000000,
+        &false,
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #26)")]
+fn test_buy_option_best_over_max_premium() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, underlying, buyer, _, _) = pair_setup(&env);
+
+    contract.buy_option_best(
+        &stable_token,
+        &underlying,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &5_2500000,
+        &200_0000000,
+        &true,
+    );
+}
+
+// Write down a pool's booked liquidity as if its accounting had gone wrong
+fn set_pool_liquidity(env: &Env, contract: &OptionsContractClient, pool_id: u64, liquidity: i128) {
+    env.as_contract(&contract.address, || {
+        env.storage()
+            .persistent()
+            .set(&DataKey::PoolTotalLiquidity(pool_id), &liquidity);
+    });
+}
+
+#[test]
+fn test_insurance_fund_covers_shortfall() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, _, buyer, conservative, aggressive) = pair_setup(&env);
+    let expiry = env.ledger().timestamp() + 86400;
+
+    // The aggressive pool's 10% fee pays a quarter into the insurance fund
+    contract.buy_option(
+        &aggressive,
+        &buyer,
+        &OptionType::Put,
+        &2000_0000000,
+        &expiry,
+        &2500000,
+    );
+    assert_eq!(contract.get_protocol_fees(&stable_token), 3750000);
+    let funder = Address::generate(&env);
+    token::StellarAssetClient::new(&env, &stable_token).mint(&funder, &1_000_0000000);
+    contract.fund_insurance(&stable_token, &funder, &299_8750000);
+    let fund = contract.get_insurance_fund(&stable_token);
+    assert_eq!(fund.balance, 300_0000000);
+    assert_eq!(fund.total_contributed, 300_0000000);
+
+    // The conservative pool's books fall 500 below the collateral it has locked
+    contract.buy_option(
+        &conservative,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &expiry,
+        &1_0000000,
+    );
+    set_pool_liquidity(&env, &contract, conservative, 1_500_0000000);
+    assert_eq!(contract.get_pool_shortfall(&conservative), 500_0000000);
+    assert_eq!(
+        contract.get_insurance_fund_health(&stable_token),
+        InsuranceFundHealth {
+            balance: 300_0000000,
+            locked_collateral: 2_500_0000000,
+            open_shortfall: 500_0000000,
+            coverage_bps: 1_200,
+        }
+    );
+
+    // The fund covers 300; the other 200 stays with the conservative pool's own LPs and
+    // the aggressive pool on the same stable token is left untouched
+    assert_eq!(contract.cover_pool_shortfall(&conservative), 300_0000000);
+    assert_eq!(contract.get_pool_shortfall(&conservative), 200_0000000);
+    assert_eq!(
+        contract.get_pool_total_liquidity(&aggressive),
+        1_005_0000000
+    );
+    let fund = contract.get_insurance_fund(&stable_token);
+    assert_eq!(fund.balance, 0);
+    assert_eq!(fund.total_covered, 300_0000000);
+    assert_eq!(
+        contract
+            .get_insurance_fund_health(&stable_token)
+            .open_shortfall,
+        200_0000000
+    );
+}
+
+#[test]
+fn test_shortfall_backstopped_on_exercise() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, price_feed, pool_id, buyer) = perpetual_setup(&env);
+    let funder = Address::generate(&env);
+    stable_token.mint(&funder, &1_000_0000000);
+    contract.fund_insurance(&stable_token.address, &funder, &1_000_0000000);
+
+    let option_id = contract.buy_option(
+        &pool_id,
+        &buyer,
+        &OptionType::Call,
+        &2000_0000000,
+        &(env.ledger().timestamp() + 86400),
+        &1_0000000,
+    );
+    set_pool_liquidity(&env, &contract, pool_id, 100_0000000);
+
+    // The 200 payout leaves the pool's books 100 negative; the fund restores them
+    price_feed.set_price(&2200_0000000);
+    assert_eq!(contract.exercise_option(&option_id), 200_0000000);
+    assert_eq!(contract.get_pool_total_liquidity(&pool_id), 0);
+    assert_eq!(
+        contract.get_insurance_fund(&stable_token.address).balance,
+        900_0000000
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #4)")]
+fn test_cover_pool_without_shortfall() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, _, _, pool_id, _) = perpetual_setup(&env);
+
+    contract.cover_pool_shortfall(&pool_id);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #5)")]
+fn test_deposit_into_drained_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract, stable_token, _, pool_id, buyer) = perpetual_setup(&env);
+
+    // Shares outstanding but nothing left to back them
+    set_pool_liquidity(&env, &contract, pool_id, 0);
+    stable_token.mint(&buyer, &100_0000000);
+    contract.provide_liquidity(&pool_id, &buyer, &100_0000000, &0);
 }